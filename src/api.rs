@@ -1,84 +1,241 @@
+use crate::logging::{self, fields, ErrorKind, Fields};
+use crate::metrics;
+use crate::ratelimit;
+use crate::transfers;
 use base64::{decode, encode};
-use chrono::{DateTime, Duration};
+use chrono::DateTime;
 use crypto::hmac::Hmac;
 use crypto::mac::Mac; // Must be in scope so we can get the hmac result
 use crypto::sha2::Sha256;
-use csv::Writer;
 use reqwest::header::HeaderMap;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde_json::value::RawValue;
 use serde_json::Value;
-use std::time::SystemTime;
-use std::{env, io, thread, time};
+use std::env;
+use std::fmt;
+use std::time::{Instant, SystemTime};
 use url::form_urlencoded::byte_serialize;
 
 static API_URL: &str = "https://api.pro.coinbase.com";
-static CANDLES_PER_REQUEST: i64 = 300;
+pub static CANDLES_PER_REQUEST: i64 = 300;
+
+/// A response body as Coinbase sent it, kept on the responses hodl records in its
+/// ledger. Debug output leaves it out; the rest of the struct already shows it
+#[derive(Clone, Default, PartialEq)]
+pub struct RawBody(pub Option<String>);
+
+impl fmt::Debug for RawBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("..")
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Tick {
-    trade_id: u64,
-    price: String,
-    size: String,
-    bid: String,
-    ask: String,
-    volume: String,
-    time: String,
+    pub trade_id: u64,
+    pub price: String,
+    pub size: String,
+    pub bid: String,
+    pub ask: String,
+    pub volume: String,
+    pub time: String,
 }
 
+/// A trade between any two users as listed by `/products/<id>/trades`, newest first
 #[derive(Debug, Deserialize, Serialize)]
-pub struct Candlestick(u64, f64, f64, f64, f64, f64);
+pub struct Trade {
+    pub time: String,
+    pub trade_id: u64,
+    pub price: String,
+    pub size: String,
+    /// The maker's side: "sell" means the trade was a buy at the ask
+    pub side: String,
+}
+
+/// Stats for the last 24 hours from `/products/<id>/stats`, with the 30-day volume
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Stats {
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub last: String,
+    pub volume: String,
+    pub volume_30day: String,
+}
+
+/// An order book as returned by `/products/<id>/book`, best prices first
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Book {
+    pub sequence: u64,
+    pub bids: Vec<BookEntry>,
+    pub asks: Vec<BookEntry>,
+}
+
+/// [ price, size, num-orders ] at levels 1 and 2; [ price, size, order_id ] at level 3
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BookEntry(pub String, pub String, pub Value);
+
+/// A single candle as returned by `/candles`: [ time, low, high, open, close, volume ]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Candlestick(pub u64, pub f64, pub f64, pub f64, pub f64, pub f64);
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Account {
-    id: String,
-    currency: String,
-    balance: String,
-    available: String,
-    hold: String,
-    profile_id: String,
+    pub id: String,
+    pub currency: String,
+    pub balance: String,
+    pub available: String,
+    pub hold: String,
+    pub profile_id: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DepositResponse {
-    id: String,
-    amount: String,
-    currency: String,
-    payout_at: String,
+    pub id: String,
+    pub amount: String,
+    pub currency: String,
+    pub payout_at: String,
+    #[serde(skip)]
+    pub raw: RawBody,
+}
+
+/// A deposit or withdrawal as listed by `/transfers`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Transfer {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+    pub canceled_at: Option<String>,
+    pub processed_at: Option<String>,
+    pub amount: String,
+    pub details: Option<Value>,
+    #[serde(skip)]
+    pub raw: RawBody,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WithdrawalResponse {
+    pub id: String,
+    pub amount: String,
+    pub currency: String,
+    pub fee: Option<String>,
+    pub subtotal: Option<String>,
+    #[serde(skip)]
+    pub raw: RawBody,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FeeEstimate {
+    pub fee: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Conversion {
+    pub id: String,
+    pub amount: String,
+    pub from_account_id: String,
+    pub to_account_id: String,
+    pub from: String,
+    pub to: String,
+    #[serde(skip)]
+    pub raw: RawBody,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Order {
-    id: String,
-    product_id: String,
-    side: String,
-    stp: String,
-    funds: String,
-    specified_funds: String,
+    pub id: String,
+    pub product_id: String,
+    pub side: String,
+    pub stp: Option<String>,
+    // Market orders are placed with funds, limit orders with a price and size
+    pub funds: Option<String>,
+    pub specified_funds: Option<String>,
+    pub price: Option<String>,
+    pub size: Option<String>,
+    // Only set on stop orders: "loss" or "entry"
+    pub stop: Option<String>,
+    pub stop_price: Option<String>,
     #[serde(rename = "type")]
-    type_name: String,
-    post_only: bool,
-    created_at: String,
-    fill_fees: String,
-    filled_size: String,
-    executed_value: String,
-    status: String,
-    settled: bool,
+    pub type_name: String,
+    pub post_only: bool,
+    pub created_at: String,
+    pub fill_fees: String,
+    pub filled_size: String,
+    pub executed_value: String,
+    pub status: String,
+    pub done_reason: Option<String>,
+    pub settled: bool,
+    #[serde(skip)]
+    pub raw: RawBody,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Fill {
+    pub trade_id: u64,
+    pub product_id: String,
+    pub order_id: String,
+    pub created_at: String,
+    pub liquidity: String,
+    pub price: String,
+    pub size: String,
+    pub fee: String,
+    pub side: String,
+    pub settled: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Product {
+    pub id: String,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub base_increment: String,
+    pub quote_increment: String,
+    pub base_min_size: Option<String>,
+    pub min_market_funds: Option<String>,
+    pub status: String,
+    #[serde(default)]
+    pub trading_disabled: bool,
+    #[serde(default)]
+    pub post_only: bool,
+    #[serde(default)]
+    pub limit_only: bool,
+    #[serde(default)]
+    pub cancel_only: bool,
+}
+
+/// A currency as listed by `/currencies`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Currency {
+    pub id: String,
+    pub name: String,
+    pub min_size: String,
+    pub max_precision: Option<String>,
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Fees {
+    pub maker_fee_rate: String,
+    pub taker_fee_rate: String,
+    pub usd_volume: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PaymentMethod {
-    id: String,
+    pub id: String,
     #[serde(rename = "type")]
-    type_name: String,
-    name: String,
-    currency: String,
-    primary_buy: bool,
-    primary_sell: bool,
-    allow_buy: bool,
-    allow_sell: bool,
-    allow_deposit: bool,
-    allow_withdraw: bool,
-    limits: Value,
+    pub type_name: String,
+    pub name: String,
+    pub currency: String,
+    pub primary_buy: bool,
+    pub primary_sell: bool,
+    pub allow_buy: bool,
+    pub allow_sell: bool,
+    pub allow_deposit: bool,
+    pub allow_withdraw: bool,
+    pub limits: Value,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -94,20 +251,40 @@ pub struct ApiError {
     message: String,
 }
 
+// Responses are matched and unpacked immediately, so variant size doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum ApiResponse {
     Account(Account),
     Accounts(Vec<Account>),
+    Transfers(Vec<Transfer>),
     ApiError(ApiError),
+    // Must precede the deposit and withdrawal responses, which share its id and amount
+    Transfer(Transfer),
     DepositResponse(DepositResponse),
+    // Must follow DepositResponse, which has the same fields plus payout_at
+    WithdrawalResponse(WithdrawalResponse),
+    Conversion(Conversion),
     Candlesticks(Vec<Candlestick>),
     Order(Order),
     Orders(Vec<Order>),
+    Fills(Vec<Fill>),
+    // Must follow Fills, whose entries have the same fields and more
+    Trades(Vec<Trade>),
     PaymentMethod(PaymentMethod),
     PaymentMethods(Vec<PaymentMethod>),
+    Product(Product),
+    Products(Vec<Product>),
+    Currencies(Vec<Currency>),
+    Fees(Fees),
+    FeeEstimate(FeeEstimate),
     ReportResponse(ReportResponse),
     Tick(Tick),
+    Book(Book),
+    Stats(Stats),
+    // Cancelling an order responds with nothing but its id
+    Id(String),
 }
 
 /// The `build_request_headers` function is responsible for creating the headers
@@ -121,31 +298,26 @@ pub enum ApiResponse {
 /// CB-ACCESS-PASSPHRASE   // The passphrase created at API key creation time
 /// ```
 fn build_request_headers(request_path: &str, method: &str, body: &str) -> Option<HeaderMap> {
-    let key = match env::var("COINBASE_API_KEY") {
-        Ok(k) => k,
+    let credential = |var: &str| match env::var(var) {
+        Ok(v) => v,
         Err(_) => {
-            eprintln!("Set the COINBASE_API_KEY environment variable to make this request");
-            std::process::exit(1);
-        }
-    };
-    let secret = match env::var("COINBASE_API_SECRET") {
-        Ok(s) => s,
-        Err(_) => {
-            eprintln!("Set the COINBASE_API_SECRET environment variable to make this request");
-            std::process::exit(1);
-        }
-    };
-    let pass = match env::var("COINBASE_API_PASSPHRASE") {
-        Ok(p) => p,
-        Err(_) => {
-            eprintln!("Set the COINBASE_API_PASSPHRASE environment variable to make this request");
+            logging::error(
+                &format!("Set the {} environment variable to make this request", var),
+                fields().error(ErrorKind::Config),
+            );
             std::process::exit(1);
         }
     };
+    let key = credential("COINBASE_API_KEY");
+    let secret = credential("COINBASE_API_SECRET");
+    let pass = credential("COINBASE_API_PASSPHRASE");
     let timestamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
         Ok(n) => n.as_secs().to_string(),
         Err(_) => {
-            eprintln!("Current system time falls before the epoch; cannot make valid request");
+            logging::error(
+                "Current system time falls before the epoch; cannot make valid request",
+                fields().error(ErrorKind::Config),
+            );
             std::process::exit(1);
         }
     };
@@ -164,45 +336,232 @@ fn build_request_headers(request_path: &str, method: &str, body: &str) -> Option
     Some(headers)
 }
 
-async fn get_request(path: &str) -> Result<ApiResponse, reqwest::Error> {
+fn observe(
+    method: &str,
+    path: &str,
+    started: Instant,
+    response: &Result<reqwest::Response, reqwest::Error>,
+) {
+    let ok = matches!(response, Ok(r) if r.status().is_success());
+    let seconds = started.elapsed().as_secs_f64();
+    metrics::observe_request(method, seconds, ok);
+    if let Ok(r) = response {
+        logging::debug(
+            &format!(
+                "{} {} responded {} in {:.3}s",
+                method,
+                path,
+                r.status(),
+                seconds
+            ),
+            fields(),
+        );
+    }
+}
+
+/// Export the balances of `accounts` as metrics
+fn record_balances(accounts: &[Account]) {
+    for a in accounts {
+        let labels = [("currency", a.currency.as_str())];
+        metrics::set(metrics::BALANCE, &labels, a.balance.parse().unwrap_or(0.0));
+        metrics::set(
+            metrics::AVAILABLE,
+            &labels,
+            a.available.parse().unwrap_or(0.0),
+        );
+    }
+}
+
+/// Count an order as placed or failed for `product_id`
+fn record_order(product_id: &str, placed: bool) {
+    let name = if placed {
+        metrics::ORDERS_PLACED
+    } else {
+        metrics::ORDERS_FAILED
+    };
+    metrics::inc(name, &[("product", product_id)], 1.0);
+}
+
+/// Why a request got no response to go on
+#[derive(Debug)]
+enum RequestError {
+    Http(reqwest::Error),
+    /// The response wasn't JSON of any shape hodl knows
+    Parse(serde_json::Error),
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(e: reqwest::Error) -> Self {
+        RequestError::Http(e)
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::Http(e) => write!(f, "{}", e),
+            RequestError::Parse(e) => write!(f, "error decoding response body: {}", e),
+        }
+    }
+}
+
+/// Parse a response body, keeping the body on the responses recorded in the ledger.
+/// A list keeps each entry's own JSON on it, as entries are recorded one by one
+fn parse_response(body: &str) -> Result<ApiResponse, RequestError> {
+    fn entries(body: &str) -> Vec<&RawValue> {
+        serde_json::from_str(body).unwrap_or_default()
+    }
+    let raw = |json: &str| RawBody(Some(String::from(json)));
+    let mut data = serde_json::from_str(body).map_err(RequestError::Parse)?;
+    match &mut data {
+        ApiResponse::Order(o) => o.raw = raw(body),
+        ApiResponse::Transfer(t) => t.raw = raw(body),
+        ApiResponse::DepositResponse(d) => d.raw = raw(body),
+        ApiResponse::WithdrawalResponse(w) => w.raw = raw(body),
+        ApiResponse::Conversion(c) => c.raw = raw(body),
+        ApiResponse::Orders(orders) => {
+            for (o, json) in orders.iter_mut().zip(entries(body)) {
+                o.raw = raw(json.get());
+            }
+        }
+        ApiResponse::Transfers(transfers) => {
+            for (t, json) in transfers.iter_mut().zip(entries(body)) {
+                t.raw = raw(json.get());
+            }
+        }
+        _ => {}
+    }
+    Ok(data)
+}
+
+async fn get_request(path: &str) -> Result<ApiResponse, RequestError> {
+    let (data, _) = get_request_page(path).await?;
+    Ok(data)
+}
+
+/// Like `get_request`, but also returns the cursor for the next (older) page of
+/// a paginated list, if there is one
+async fn get_request_page(path: &str) -> Result<(ApiResponse, Option<String>), RequestError> {
+    ratelimit::wait_turn().await;
     let headers = build_request_headers(path, "GET", "").unwrap();
     let client = Client::builder().user_agent("hodl").build()?;
     let request_url = format!("{api}{path}", api = API_URL, path = path);
-    let response = client.get(&request_url).headers(headers).send().await?;
-    let data = response.json::<ApiResponse>().await?;
-    Ok(data)
+    let started = Instant::now();
+    let response = client.get(&request_url).headers(headers).send().await;
+    observe("GET", path, started, &response);
+    let response = response?;
+    let after = response
+        .headers()
+        .get("cb-after")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from);
+    let data = parse_response(&response.text().await?)?;
+    Ok((data, after))
 }
 
-async fn post_request(
-    path: &str,
-    body: String,
-    json: Value,
-) -> Result<ApiResponse, reqwest::Error> {
+async fn post_request(path: &str, body: String, json: Value) -> Result<ApiResponse, RequestError> {
+    ratelimit::wait_turn().await;
     let headers = build_request_headers(path, "POST", &body[..]).unwrap();
     let client = Client::builder().user_agent("hodl").build()?;
     let request_url = format!("{api}{path}", api = API_URL, path = path);
+    let started = Instant::now();
     let response = client
         .post(&request_url)
         .json(&json)
         .headers(headers)
         .send()
-        .await?;
-    let data = response.json::<ApiResponse>().await?;
-    Ok(data)
+        .await;
+    observe("POST", path, started, &response);
+    let response = response?;
+    parse_response(&response.text().await?)
 }
 
-pub async fn print_balance(currency: Option<&str>) {
-    let path = "/accounts";
-    let accounts = match get_request(path).await.unwrap() {
-        ApiResponse::Accounts(a) => a,
-        ApiResponse::ApiError(e) => {
-            eprintln!("Error message from Coinbase API: {:?}", e.message);
-            std::process::exit(1);
+async fn delete_request(path: &str) -> Result<ApiResponse, RequestError> {
+    ratelimit::wait_turn().await;
+    let headers = build_request_headers(path, "DELETE", "").unwrap();
+    let client = Client::builder().user_agent("hodl").build()?;
+    let request_url = format!("{api}{path}", api = API_URL, path = path);
+    let started = Instant::now();
+    let response = client.delete(&request_url).headers(headers).send().await;
+    observe("DELETE", path, started, &response);
+    let response = response?;
+    parse_response(&response.text().await?)
+}
+
+/// Log why a request to `action` failed: no usable response, an error message from
+/// Coinbase, or a response of the wrong kind
+fn log_failure(action: &str, response: &Result<ApiResponse, RequestError>, fields: Fields) {
+    let (message, kind) = match response {
+        Err(RequestError::Http(e)) => (format!("Failed to {}: {}", action, e), ErrorKind::Http),
+        Err(e) => (
+            format!("Failed to {}: {}", action, e),
+            ErrorKind::Unexpected,
+        ),
+        Ok(ApiResponse::ApiError(e)) => (
+            format!(
+                "Failed to {}; error from Coinbase API: {}",
+                action, e.message
+            ),
+            ErrorKind::Api,
+        ),
+        Ok(_) => (
+            format!(
+                "Failed to {}: unexpected response; log into Coinbase and check",
+                action
+            ),
+            ErrorKind::Unexpected,
+        ),
+    };
+    logging::error(&message, fields.error(kind));
+}
+
+/// Parse a hand-written request body, logging it if it isn't valid JSON
+fn parse_payload(payload: &str, fields: Fields) -> Option<Value> {
+    match serde_json::from_str(payload) {
+        Ok(j) => Some(j),
+        Err(e) => {
+            logging::error(
+                &format!("Failed to parse the following as JSON: {} ({})", payload, e),
+                fields.error(ErrorKind::InvalidRequest),
+            );
+            None
         }
-        _ => {
-            eprintln!("Failed to request account information");
-            std::process::exit(1);
+    }
+}
+
+/// The fields describing an order
+pub fn order_fields(order: &Order) -> Fields {
+    let amount = order
+        .funds
+        .as_deref()
+        .or(order.specified_funds.as_deref())
+        .or(order.size.as_deref())
+        .unwrap_or(&order.executed_value);
+    fields()
+        .product(&order.product_id)
+        .amount(amount)
+        .order_id(&order.id)
+        .status(&order.status)
+}
+
+pub async fn get_accounts() -> Option<Vec<Account>> {
+    match get_request("/accounts").await {
+        Ok(ApiResponse::Accounts(a)) => {
+            record_balances(&a);
+            logging::debug(&format!("Fetched {} accounts", a.len()), fields());
+            Some(a)
+        }
+        response => {
+            log_failure("request account information", &response, fields());
+            None
         }
+    }
+}
+
+pub async fn print_balance(currency: Option<&str>) {
+    let accounts = match get_accounts().await {
+        Some(a) => a,
+        None => std::process::exit(1),
     };
 
     if let Some(c) = currency {
@@ -211,38 +570,128 @@ pub async fn print_balance(currency: Option<&str>) {
             println!("{:#?}", a);
             return;
         } else {
-            eprintln!("No account found containing {}", c);
+            logging::error(
+                &format!("No account found containing {}", c),
+                fields().product(c).error(ErrorKind::Unexpected),
+            );
             std::process::exit(1);
         }
     }
     println!("{:#?}", accounts);
 }
 
+pub async fn get_payment_methods() -> Option<Vec<PaymentMethod>> {
+    match get_request("/payment-methods").await {
+        Ok(ApiResponse::PaymentMethods(a)) => {
+            logging::debug(&format!("Fetched {} payment methods", a.len()), fields());
+            Some(a)
+        }
+        response => {
+            log_failure("fetch payment methods", &response, fields());
+            None
+        }
+    }
+}
+
 pub async fn print_payment_methods() {
-    let path = "/payment-methods";
-    let response = match get_request(path).await.unwrap() {
-        ApiResponse::PaymentMethods(a) => a,
-        ApiResponse::ApiError(e) => {
-            eprintln!("Error message from Coinbase API: {:?}", e.message);
-            std::process::exit(1);
+    match get_payment_methods().await {
+        Some(methods) => println!("Payment methods: {:#?}", methods),
+        None => std::process::exit(1),
+    }
+}
+
+pub async fn make_deposit(amount: &f64, bank_id: &str) -> Option<DepositResponse> {
+    let payload = format!(
+        r#"{{
+    "amount": {amount},
+    "currency": "USD",
+    "payment_method_id": "{bank_id}"
+}}"#,
+        amount = amount,
+        bank_id = bank_id
+    );
+    let event = fields().product("USD").amount(amount);
+    let json = parse_payload(&payload, event.clone())?;
+    let path = "/deposits/payment-method";
+    let body: String = json.to_string();
+    match post_request(path, body, json).await {
+        Ok(ApiResponse::DepositResponse(r)) => {
+            logging::info(
+                &format!("Deposit {} initiated", r.id),
+                event.status("created"),
+            );
+            Some(r)
         }
-        _ => {
-            eprintln!("Failed to fetch payment methods");
-            std::process::exit(1);
+        response => {
+            log_failure("deposit", &response, event);
+            None
         }
-    };
-    println!("Payment methods: {:#?}", response);
+    }
 }
 
-pub async fn make_deposit(amount: &f64) -> Option<DepositResponse> {
-    let bank_id = match env::var("BANK_ID") {
-        Ok(k) => k,
-        Err(_) => {
-            eprintln!("You must set the BANK_ID environment variable to make deposits.");
-            eprintln!("Looking for your bank id? Use the 'payment-methods' command");
-            std::process::exit(1);
+/// List every deposit and withdrawal, optionally only those of `transfer_type`
+/// ("deposit" or "withdraw"), following the pagination cursor to the oldest
+pub async fn list_transfers(transfer_type: Option<&str>) -> Option<Vec<Transfer>> {
+    let mut transfers = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let mut query = Vec::new();
+        if let Some(t) = transfer_type {
+            query.push(format!("type={}", t));
         }
-    };
+        if let Some(a) = &after {
+            query.push(format!("after={}", a));
+        }
+        let mut path = String::from("/transfers");
+        if !query.is_empty() {
+            path = format!("{}?{}", path, query.join("&"));
+        }
+        let (response, next) = match get_request_page(&path[..]).await {
+            Ok((r, next)) => (Ok(r), next),
+            Err(e) => (Err(e), None),
+        };
+        match response {
+            Ok(ApiResponse::Transfers(t)) if !t.is_empty() => transfers.extend(t),
+            response @ Ok(ApiResponse::ApiError(_)) | response @ Err(_) => {
+                log_failure("fetch transfers", &response, fields());
+                return None;
+            }
+            // An empty page parses as whichever list variant comes first
+            _ => break,
+        }
+        match next {
+            Some(n) if after.as_ref() != Some(&n) => after = Some(n),
+            _ => break,
+        }
+    }
+    logging::debug(&format!("Fetched {} transfers", transfers.len()), fields());
+    Some(transfers)
+}
+
+pub async fn get_transfer(transfer_id: &str) -> Option<Transfer> {
+    let path = format!("/transfers/{}", transfer_id);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Transfer(t)) => {
+            let status = format!("{:?}", transfers::status(&t)).to_lowercase();
+            logging::debug(
+                &format!("Fetched transfer {}", transfer_id),
+                fields().amount(&t.amount).status(&status),
+            );
+            Some(t)
+        }
+        response => {
+            log_failure(
+                &format!("fetch transfer {}", transfer_id),
+                &response,
+                fields(),
+            );
+            None
+        }
+    }
+}
+
+/// Withdraw USD to a bank account; the response has the same shape as a deposit's
+pub async fn withdraw_fiat(amount: &f64, bank_id: &str) -> Option<DepositResponse> {
     let payload = format!(
         r#"{{
     "amount": {amount},
@@ -252,25 +701,111 @@ pub async fn make_deposit(amount: &f64) -> Option<DepositResponse> {
         amount = amount,
         bank_id = bank_id
     );
-    let json: Value = match serde_json::from_str(&payload) {
-        Ok(j) => j,
-        Err(e) => {
-            eprintln!("Failed to parse the following as JSON:");
-            eprintln!("{}", payload);
-            eprintln!("{:?}", e);
-            return None;
+    let event = fields().product("USD").amount(amount);
+    let json = parse_payload(&payload, event.clone())?;
+    let path = "/withdrawals/payment-method";
+    let body: String = json.to_string();
+    match post_request(path, body, json).await {
+        Ok(ApiResponse::DepositResponse(r)) => {
+            logging::info(
+                &format!("Withdrawal {} initiated", r.id),
+                event.status("created"),
+            );
+            Some(r)
         }
-    };
-    let path = "/deposits/payment-method";
+        response => {
+            log_failure("withdraw", &response, event);
+            None
+        }
+    }
+}
+
+/// Estimate the network fee of withdrawing `currency` to `address`
+pub async fn get_withdrawal_fee_estimate(currency: &str, address: &str) -> Option<FeeEstimate> {
+    let a: String = byte_serialize(address.as_bytes()).collect();
+    let path = format!(
+        "/withdrawals/fee-estimate?currency={}&crypto_address={}",
+        currency, a
+    );
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::FeeEstimate(f)) => {
+            logging::debug(
+                &format!("Withdrawal fee estimate is {}", f.fee),
+                fields().product(currency),
+            );
+            Some(f)
+        }
+        response => {
+            log_failure(
+                "estimate withdrawal fee",
+                &response,
+                fields().product(currency),
+            );
+            None
+        }
+    }
+}
+
+/// Withdraw cryptocurrency to an external address. Currencies that use destination
+/// tags (XRP, XLM, ...) are sent with `no_destination_tag` when no tag is given.
+pub async fn withdraw_crypto(
+    amount: &f64,
+    currency: &str,
+    address: &str,
+    destination_tag: Option<&str>,
+    uses_tag: bool,
+) -> Option<WithdrawalResponse> {
+    let mut json = serde_json::json!({
+        "amount": amount.to_string(),
+        "currency": currency,
+        "crypto_address": address,
+    });
+    match destination_tag {
+        Some(t) => json["destination_tag"] = Value::from(t),
+        None if uses_tag => json["no_destination_tag"] = Value::from(true),
+        None => {}
+    }
+    let event = fields().product(currency).amount(amount);
+    let path = "/withdrawals/crypto";
     let body: String = json.to_string();
-    match post_request(path, body, json).await.unwrap() {
-        ApiResponse::DepositResponse(r) => Some(r),
-        ApiResponse::ApiError(e) => {
-            eprintln!("Deposit failed; error from Coinbase API: {:?}", e.message);
+    match post_request(path, body, json).await {
+        Ok(ApiResponse::WithdrawalResponse(r)) => {
+            logging::info(
+                &format!("Withdrawal {} to {} initiated", r.id, address),
+                event.status("created"),
+            );
+            Some(r)
+        }
+        response => {
+            log_failure("withdraw", &response, event);
             None
         }
-        _ => {
-            eprintln!("Deposit failed for unknown reason");
+    }
+}
+
+/// Convert between USD and a stablecoin (ex: USD -> USDC) at 1:1
+pub async fn convert(from: &str, to: &str, amount: &f64) -> Option<Conversion> {
+    let payload = format!(
+        r#"{{
+    "from": "{from}",
+    "to": "{to}",
+    "amount": "{amount}"
+}}"#,
+        from = from,
+        to = to,
+        amount = amount
+    );
+    let event = fields().product(&format!("{}-{}", from, to)).amount(amount);
+    let json = parse_payload(&payload, event.clone())?;
+    let path = "/conversions";
+    let body: String = json.to_string();
+    match post_request(path, body, json).await {
+        Ok(ApiResponse::Conversion(r)) => {
+            logging::info(&format!("Conversion {} done", r.id), event);
+            Some(r)
+        }
+        response => {
+            log_failure("convert", &response, event);
             None
         }
     }
@@ -288,62 +823,374 @@ pub async fn place_order(amount: &f64, currency: &str) -> Option<Order> {
         amount = amount,
         product_id = product_id
     );
-    let json: Value = match serde_json::from_str(&payload) {
-        Ok(j) => j,
-        Err(e) => {
-            eprintln!("Failed to parse the following as JSON:");
-            eprintln!("{}", payload);
-            eprintln!("{:?}", e);
-            return None;
+    let event = fields().product(product_id).amount(amount);
+    let json = parse_payload(&payload, event.clone())?;
+    let path = "/orders";
+    let body: String = json.to_string();
+    let response = post_request(path, body, json).await;
+    record_order(product_id, matches!(response, Ok(ApiResponse::Order(_))));
+    match response {
+        Ok(ApiResponse::Order(r)) => {
+            logging::info("Market order placed", order_fields(&r));
+            Some(r)
         }
-    };
+        response => {
+            log_failure("purchase", &response, event);
+            None
+        }
+    }
+}
+
+/// Place a limit order; with `post_only` the order is rejected rather than taking liquidity
+pub async fn place_limit_order(
+    product_id: &str,
+    side: &str,
+    price: &str,
+    size: &str,
+    post_only: bool,
+) -> Option<Order> {
+    let payload = format!(
+        r#"{{
+    "type": "limit",
+    "side": "{side}",
+    "product_id": "{product_id}",
+    "price": "{price}",
+    "size": "{size}",
+    "post_only": {post_only}
+}}"#,
+        side = side,
+        product_id = product_id,
+        price = price,
+        size = size,
+        post_only = post_only
+    );
+    let event = fields().product(product_id).amount(size);
+    let json = parse_payload(&payload, event.clone())?;
     let path = "/orders";
     let body: String = json.to_string();
-    match post_request(path, body, json).await.unwrap() {
-        ApiResponse::Order(r) => Some(r),
-        ApiResponse::ApiError(e) => {
-            eprintln!("Purcahse failed; error from Coinbase API: {:?}", e.message);
+    let response = post_request(path, body, json).await;
+    record_order(product_id, matches!(response, Ok(ApiResponse::Order(_))));
+    match response {
+        Ok(ApiResponse::Order(r)) => {
+            logging::info(
+                &format!("Limit order to {} at {} placed", side, price),
+                order_fields(&r),
+            );
+            Some(r)
+        }
+        response => {
+            log_failure("place limit order", &response, event);
             None
         }
-        _ => {
-            eprintln!("Something unexpected happened; log into Coinbase and check");
+    }
+}
+
+/// Place a stop order that triggers once the last trade price crosses `stop_price`.
+/// Without a `limit_price` the triggered order is a market order.
+pub async fn place_stop_order(
+    product_id: &str,
+    side: &str,
+    stop: &str,
+    stop_price: &str,
+    size: Option<&str>,
+    funds: Option<&str>,
+    limit_price: Option<&str>,
+) -> Option<Order> {
+    let mut json = serde_json::json!({
+        "type": if limit_price.is_some() { "limit" } else { "market" },
+        "side": side,
+        "product_id": product_id,
+        "stop": stop,
+        "stop_price": stop_price,
+    });
+    if let Some(p) = limit_price {
+        json["price"] = Value::from(p);
+    }
+    if let Some(s) = size {
+        json["size"] = Value::from(s);
+    }
+    if let Some(f) = funds {
+        json["funds"] = Value::from(f);
+    }
+    let event = fields()
+        .product(product_id)
+        .amount(funds.or(size).unwrap_or(""));
+    let path = "/orders";
+    let body: String = json.to_string();
+    let response = post_request(path, body, json).await;
+    record_order(product_id, matches!(response, Ok(ApiResponse::Order(_))));
+    match response {
+        Ok(ApiResponse::Order(r)) => {
+            logging::info(
+                &format!("Stop-{} order at {} placed", stop, stop_price),
+                order_fields(&r),
+            );
+            Some(r)
+        }
+        response => {
+            log_failure("place stop order", &response, event);
             None
         }
     }
 }
 
+pub async fn get_order(order_id: &str) -> Option<Order> {
+    let path = format!("/orders/{}", order_id);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Order(o)) => {
+            logging::debug("Fetched order", order_fields(&o));
+            Some(o)
+        }
+        response => {
+            log_failure("fetch order", &response, fields().order_id(order_id));
+            None
+        }
+    }
+}
+
+/// Cancel an open order, returning whether the exchange accepted the cancellation
+pub async fn cancel_order(order_id: &str) -> bool {
+    let path = format!("/orders/{}", order_id);
+    match delete_request(&path[..]).await {
+        Ok(ApiResponse::Id(_)) => {
+            logging::info(
+                "Order canceled",
+                fields().order_id(order_id).status("canceled"),
+            );
+            true
+        }
+        response => {
+            log_failure("cancel order", &response, fields().order_id(order_id));
+            false
+        }
+    }
+}
+
 pub async fn list_orders(product_id: Option<&str>) -> Option<Vec<Order>> {
     let mut path = String::from("/orders");
     if let Some(pid) = product_id {
         path = format!("{}?product_id={}", path, pid);
     }
-    let response = get_request(&path[..]).await.unwrap();
-    match response {
-        ApiResponse::Orders(o) => Some(o),
-        ApiResponse::ApiError(e) => {
-            eprintln!("Failed to fetch order information {:?}", e.message);
+    let event = match product_id {
+        Some(p) => fields().product(p),
+        None => fields(),
+    };
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Orders(o)) => {
+            logging::debug(&format!("Fetched {} open orders", o.len()), event);
+            Some(o)
+        }
+        response @ Ok(ApiResponse::ApiError(_)) | response @ Err(_) => {
+            log_failure("fetch order information", &response, event);
             None
         }
         _ => Some(Vec::new()),
     }
 }
 
+/// Fetch every fill for a product, following the pagination cursor to the oldest
+pub async fn list_fills(product_id: &str) -> Option<Vec<Fill>> {
+    let mut fills = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let mut path = format!("/fills?product_id={}", product_id);
+        if let Some(a) = &after {
+            path = format!("{}&after={}", path, a);
+        }
+        let (response, next) = match get_request_page(&path[..]).await {
+            Ok((r, next)) => (Ok(r), next),
+            Err(e) => (Err(e), None),
+        };
+        match response {
+            Ok(ApiResponse::Fills(f)) if !f.is_empty() => fills.extend(f),
+            response @ Ok(ApiResponse::ApiError(_)) | response @ Err(_) => {
+                log_failure("fetch fills", &response, fields().product(product_id));
+                return None;
+            }
+            // An empty page parses as whichever list variant comes first
+            _ => break,
+        }
+        match next {
+            Some(n) if after.as_ref() != Some(&n) => after = Some(n),
+            _ => break,
+        }
+    }
+    logging::debug(
+        &format!("Fetched {} fills", fills.len()),
+        fields().product(product_id),
+    );
+    Some(fills)
+}
+
 /// Check the current exchange rate of products on the Coinbase Pro API
 pub async fn get_tick(product_id: &str) -> Option<Tick> {
     let path = format!("/products/{}/ticker", product_id);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Tick(t)) => {
+            logging::debug(
+                &format!("Last trade at {}", t.price),
+                fields().product(product_id),
+            );
+            Some(t)
+        }
+        response => {
+            log_failure("fetch tick data", &response, fields().product(product_id));
+            None
+        }
+    }
+}
 
-    let response = get_request(&path[..]).await.unwrap();
-    match response {
-        ApiResponse::Tick(t) => Some(t),
-        ApiResponse::ApiError(e) => {
-            eprintln!(
-                "Failed to fetch tick data for {}: {:?}",
-                product_id, e.message
+/// Fetch the most recent `limit` trades for a product, following the pagination cursor
+pub async fn list_trades(product_id: &str, limit: usize) -> Option<Vec<Trade>> {
+    let mut trades = Vec::new();
+    let mut after: Option<String> = None;
+    while trades.len() < limit {
+        // Pages hold at most 100 trades
+        let page_size = (limit - trades.len()).min(100);
+        let mut path = format!("/products/{}/trades?limit={}", product_id, page_size);
+        if let Some(a) = &after {
+            path = format!("{}&after={}", path, a);
+        }
+        let (response, next) = match get_request_page(&path[..]).await {
+            Ok((r, next)) => (Ok(r), next),
+            Err(e) => (Err(e), None),
+        };
+        match response {
+            Ok(ApiResponse::Trades(t)) if !t.is_empty() => trades.extend(t),
+            response @ Ok(ApiResponse::ApiError(_)) | response @ Err(_) => {
+                log_failure("fetch trades", &response, fields().product(product_id));
+                return None;
+            }
+            // An empty page parses as whichever list variant comes first
+            _ => break,
+        }
+        match next {
+            Some(n) if after.as_ref() != Some(&n) => after = Some(n),
+            _ => break,
+        }
+    }
+    trades.truncate(limit);
+    logging::debug(
+        &format!("Fetched {} trades", trades.len()),
+        fields().product(product_id),
+    );
+    Some(trades)
+}
+
+pub async fn get_stats(product_id: &str) -> Option<Stats> {
+    let path = format!("/products/{}/stats", product_id);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Stats(s)) => {
+            logging::debug("Fetched 24 hour stats", fields().product(product_id));
+            Some(s)
+        }
+        response => {
+            log_failure("fetch stats", &response, fields().product(product_id));
+            None
+        }
+    }
+}
+
+/// Fetch the order book for a product: the best bid and ask at level 1, the top 50
+/// price levels at level 2, or every open order at level 3
+pub async fn get_book(product_id: &str, level: u8) -> Option<Book> {
+    let path = format!("/products/{}/book?level={}", product_id, level);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Book(b)) => {
+            logging::debug(
+                &format!("Fetched {} bids and {} asks", b.bids.len(), b.asks.len()),
+                fields().product(product_id),
+            );
+            Some(b)
+        }
+        response => {
+            log_failure("fetch order book", &response, fields().product(product_id));
+            None
+        }
+    }
+}
+
+/// Fetch up to 300 candles for a product; the API returns them newest first
+pub async fn get_candles(
+    product_id: &str,
+    start: &str,
+    end: &str,
+    granularity: i64,
+) -> Option<Vec<Candlestick>> {
+    let s: String = byte_serialize(start.as_bytes()).collect();
+    let e: String = byte_serialize(end.as_bytes()).collect();
+    let path = format!(
+        "/products/{}/candles?start={}&end={}&granularity={}",
+        product_id, s, e, granularity
+    );
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Candlesticks(c)) => {
+            logging::debug(
+                &format!("Fetched {} candles", c.len()),
+                fields().product(product_id),
             );
+            Some(c)
+        }
+        // A window without candles is an empty list, which parses as whichever list
+        // variant comes first
+        Ok(ApiResponse::Accounts(a)) if a.is_empty() => Some(Vec::new()),
+        response => {
+            log_failure("fetch candles", &response, fields().product(product_id));
+            None
+        }
+    }
+}
+
+pub async fn get_product(product_id: &str) -> Option<Product> {
+    let path = format!("/products/{}", product_id);
+    match get_request(&path[..]).await {
+        Ok(ApiResponse::Product(p)) => {
+            logging::debug("Fetched product", fields().product(product_id));
+            Some(p)
+        }
+        response => {
+            log_failure("fetch product", &response, fields().product(product_id));
             None
         }
-        _ => {
-            eprintln!("Failed to fetch tick data for {}", product_id);
+    }
+}
+
+/// List every product on the exchange, tradable or not
+pub async fn list_products() -> Option<Vec<Product>> {
+    match get_request("/products").await {
+        Ok(ApiResponse::Products(p)) => {
+            logging::debug(&format!("Fetched {} products", p.len()), fields());
+            Some(p)
+        }
+        response => {
+            log_failure("fetch products", &response, fields());
+            None
+        }
+    }
+}
+
+pub async fn list_currencies() -> Option<Vec<Currency>> {
+    match get_request("/currencies").await {
+        Ok(ApiResponse::Currencies(c)) => {
+            logging::debug(&format!("Fetched {} currencies", c.len()), fields());
+            Some(c)
+        }
+        response => {
+            log_failure("fetch currencies", &response, fields());
+            None
+        }
+    }
+}
+
+/// Fetch the maker/taker fee rates of the current fee tier
+pub async fn get_fees() -> Option<Fees> {
+    match get_request("/fees").await {
+        Ok(ApiResponse::Fees(f)) => {
+            logging::debug("Fetched fee rates", fields());
+            Some(f)
+        }
+        response => {
+            log_failure("fetch fee rates", &response, fields());
             None
         }
     }
@@ -365,7 +1212,7 @@ fn build_history_url(product_id: &str, start: &str, end: &str, granularity: &str
     )
 }
 
-fn calc_num_requests(start: &str, end: &str, candle_size: i64) -> i64 {
+pub fn calc_num_requests(start: &str, end: &str, candle_size: i64) -> i64 {
     let start_date = DateTime::parse_from_rfc3339(start).expect("Failed to parse start date");
     let end_date = DateTime::parse_from_rfc3339(end).expect("Failed to parse end date");
     let duration: i64 = (end_date - start_date).num_seconds();
@@ -373,49 +1220,69 @@ fn calc_num_requests(start: &str, end: &str, candle_size: i64) -> i64 {
     num_requests
 }
 
-pub async fn get_history(
+/// Why a window of candles couldn't be fetched
+#[derive(Debug)]
+pub struct HistoryError {
+    pub message: String,
+    /// Whether trying again could help: the request got no response, Coinbase failed
+    /// on its side (5xx) or it asked for fewer requests (429)
+    pub retryable: bool,
+}
+
+fn history_error(message: String, retryable: bool) -> HistoryError {
+    HistoryError { message, retryable }
+}
+
+/// Fetch one window of candles for `history`. This uses the public API, so it needs no
+/// credentials and has its own rate limit; the candles come back newest first
+pub async fn get_history_window(
+    client: &Client,
     product_id: &str,
     start: &str,
     end: &str,
     granularity: &str,
-    mut writer: Writer<io::Stdout>,
-) -> Result<(), reqwest::Error> {
-    let candle_size = granularity
-        .parse::<i64>()
-        .expect("Granularity must be a number (in seconds)");
-    let num_requests = calc_num_requests(start, end, candle_size);
-    let client = Client::builder().user_agent("hodl").build()?;
-
-    for i in 0..num_requests {
-        let start_dt = DateTime::parse_from_rfc3339(start).expect("Failed to parse start date");
-        let request_start = start_dt + Duration::seconds(i * candle_size * CANDLES_PER_REQUEST);
-        let request_end = request_start + Duration::seconds(candle_size * CANDLES_PER_REQUEST);
-        let request_url = build_history_url(
-            product_id,
-            &request_start.to_string(),
-            &request_end.to_string(),
-            granularity,
-        );
-
-        if let ApiResponse::Candlesticks(candlesticks) = client
-            .get(&request_url)
-            .send()
-            .await?
-            .json::<super::ApiResponse>()
-            .await?
-        {
-            for c in candlesticks {
-                writer
-                    .serialize(c)
-                    .expect("Failed to write candlestick to CSV");
-                writer.flush().expect("Failed to flush CSV writer");
-            }
-        };
+) -> Result<Vec<Candlestick>, HistoryError> {
+    let request_url = build_history_url(product_id, start, end, granularity);
+    let response = client
+        .get(&request_url)
+        .send()
+        .await
+        .map_err(|e| history_error(format!("Failed to fetch candles: {}", e), true))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| history_error(format!("Failed to read candles: {}", e), true))?;
+    parse_history_window(status, &body)
+}
 
-        // API is rate limited to 1 request per second
-        thread::sleep(time::Duration::from_millis(1000));
+fn parse_history_window(status: StatusCode, body: &str) -> Result<Vec<Candlestick>, HistoryError> {
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(history_error(
+            format!("Failed to fetch candles: {}", status),
+            true,
+        ));
+    }
+    match serde_json::from_str(body) {
+        Ok(ApiResponse::Candlesticks(c)) => Ok(c),
+        Ok(ApiResponse::ApiError(e)) => Err(history_error(
+            format!(
+                "Failed to fetch candles; error from Coinbase API: {}",
+                e.message
+            ),
+            false,
+        )),
+        // An empty list parses as whichever list variant comes first
+        Ok(ApiResponse::Accounts(a)) if a.is_empty() => Ok(Vec::new()),
+        Ok(_) => Err(history_error(
+            String::from("Failed to fetch candles: unexpected response"),
+            false,
+        )),
+        Err(e) => Err(history_error(
+            format!("Failed to read candles: {}", e),
+            false,
+        )),
     }
-    Ok(())
 }
 
 pub async fn request_report(
@@ -437,28 +1304,17 @@ pub async fn request_report(
         account = account,
         email = email,
     );
-    let json: Value = match serde_json::from_str(&payload) {
-        Ok(j) => j,
-        Err(e) => {
-            eprintln!("Failed to parse the following as JSON:");
-            eprintln!("{}", payload);
-            eprintln!("{:?}", e);
-            return None;
-        }
-    };
+    let event = fields();
+    let json = parse_payload(&payload, event.clone())?;
     let path = "/reports";
     let body: String = json.to_string();
-    match post_request(path, body, json).await.unwrap() {
-        ApiResponse::ReportResponse(r) => Some(r),
-        ApiResponse::ApiError(e) => {
-            eprintln!(
-                "Report request failed; error from Coinbase API: {:?}",
-                e.message
-            );
-            None
+    match post_request(path, body, json).await {
+        Ok(ApiResponse::ReportResponse(r)) => {
+            logging::info(&format!("Report requested: {:?}", r), event);
+            Some(r)
         }
-        _ => {
-            eprintln!("Report request failed for unknown reason");
+        response => {
+            log_failure("request report", &response, event);
             None
         }
     }
@@ -35,3 +35,180 @@ fn test_calc_num_requets() {
         2
     );
 }
+
+#[test]
+fn test_parse_conversion() {
+    let json = r#"{
+        "id": "8942caee-f9d5-4600-a894-4811268545db",
+        "amount": "10000.00",
+        "from_account_id": "7849cc79-8b01-4793-9345-bc6b5f08acce",
+        "to_account_id": "105c3e58-0898-4106-8283-dc5781cda07b",
+        "from": "USD",
+        "to": "USDC"
+    }"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Conversion(c) => assert_eq!(c.to, "USDC"),
+        r => panic!("Parsed a conversion as {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_transfers() {
+    let json = r#"[{
+        "id": "19ac524d-8827-4246-a1b2-18dc5ca9472c",
+        "type": "deposit",
+        "created_at": "2020-03-12 00:14:12.397805+00",
+        "completed_at": null,
+        "canceled_at": null,
+        "processed_at": null,
+        "user_nonce": null,
+        "amount": "50.00000000",
+        "details": {}
+    }]"#;
+    match parse_response(json).unwrap() {
+        ApiResponse::Transfers(t) => {
+            assert_eq!(t[0].amount, "50.00000000");
+            // Each entry keeps its JSON as sent, fields hodl doesn't use included
+            assert!(t[0]
+                .raw
+                .0
+                .as_ref()
+                .unwrap()
+                .contains("\"user_nonce\": null"));
+        }
+        r => panic!("Parsed transfers as {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_response_keeps_body() {
+    let json = r#"{
+        "id": "593533d2-ff31-46e0-b22e-ca754147a96a",
+        "amount": "10.00",
+        "currency": "USD",
+        "payout_at": "2016-08-20T00:31:09Z"
+    }"#;
+    match parse_response(json).unwrap() {
+        ApiResponse::DepositResponse(d) => assert_eq!(d.raw.0.as_deref(), Some(json)),
+        r => panic!("Parsed a deposit as {:?}", r),
+    }
+    assert!(matches!(
+        parse_response("<html>Bad gateway</html>"),
+        Err(RequestError::Parse(_))
+    ));
+}
+
+#[test]
+fn test_parse_book() {
+    let json = r#"{
+        "sequence": 3,
+        "bids": [["295.96", "4.39088265", 2]],
+        "asks": [["295.97", "25.23542881", "da863862-25f4-4868-ac41-005d11ab0a5f"]]
+    }"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Book(b) => {
+            assert_eq!(b.bids[0].0, "295.96");
+            assert_eq!(b.asks[0].1, "25.23542881");
+        }
+        r => panic!("Parsed a book as {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_trades_and_stats() {
+    let json = r#"[{
+        "time": "2014-11-07T22:19:28.578544Z",
+        "trade_id": 74,
+        "price": "10.00000000",
+        "size": "0.01000000",
+        "side": "buy"
+    }]"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Trades(t) => assert_eq!(t[0].trade_id, 74),
+        r => panic!("Parsed trades as {:?}", r),
+    }
+    let json = r#"{
+        "open": "6745.61000000",
+        "high": "7292.11000000",
+        "low": "6650.00000000",
+        "last": "6813.19000000",
+        "volume": "26185.51325269",
+        "volume_30day": "1019451.11188405"
+    }"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Stats(s) => assert_eq!(s.volume_30day, "1019451.11188405"),
+        r => panic!("Parsed stats as {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_products_and_currencies() {
+    let json = r#"[{
+        "id": "BTC-USD",
+        "display_name": "BTC/USD",
+        "base_currency": "BTC",
+        "quote_currency": "USD",
+        "base_increment": "0.00000001",
+        "quote_increment": "0.01000000",
+        "base_min_size": "0.00100000",
+        "base_max_size": "280.00000000",
+        "min_market_funds": "5",
+        "max_market_funds": "1000000",
+        "status": "online",
+        "status_message": "",
+        "cancel_only": false,
+        "limit_only": false,
+        "post_only": false,
+        "trading_disabled": false
+    }]"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Products(p) => assert_eq!(p[0].min_market_funds.as_deref(), Some("5")),
+        r => panic!("Parsed products as {:?}", r),
+    }
+    let json = r#"[{
+        "id": "BTC",
+        "name": "Bitcoin",
+        "min_size": "0.00000001",
+        "status": "online",
+        "message": null,
+        "max_precision": "0.00000001",
+        "details": {}
+    }]"#;
+    match serde_json::from_str::<ApiResponse>(json).unwrap() {
+        ApiResponse::Currencies(c) => assert_eq!(c[0].name, "Bitcoin"),
+        r => panic!("Parsed currencies as {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_history_window() {
+    let candles = parse_history_window(StatusCode::OK, "[[60, 1.0, 2.0, 1.5, 1.75, 10.0]]");
+    assert_eq!(candles.unwrap()[0].0, 60);
+    assert!(parse_history_window(StatusCode::OK, "[]")
+        .unwrap()
+        .is_empty());
+
+    // Coinbase's own failures and rate limiting pass; a bad request fails the same way again
+    let body = r#"{"message": "Internal server error"}"#;
+    assert!(
+        parse_history_window(StatusCode::SERVICE_UNAVAILABLE, "<html></html>")
+            .unwrap_err()
+            .retryable
+    );
+    assert!(
+        parse_history_window(StatusCode::INTERNAL_SERVER_ERROR, body)
+            .unwrap_err()
+            .retryable
+    );
+    assert!(
+        parse_history_window(StatusCode::TOO_MANY_REQUESTS, body)
+            .unwrap_err()
+            .retryable
+    );
+    let unknown =
+        parse_history_window(StatusCode::NOT_FOUND, r#"{"message": "NotFound"}"#).unwrap_err();
+    assert!(!unknown.retryable);
+    assert!(unknown
+        .message
+        .ends_with("error from Coinbase API: NotFound"));
+}
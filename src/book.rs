@@ -0,0 +1,127 @@
+use crate::api::{Book, BookEntry};
+use serde_json::Value;
+
+/// One row of one side of the book, with the size available at it and every better price
+#[derive(Debug, PartialEq)]
+pub struct Level {
+    pub price: String,
+    pub size: String,
+    pub cumulative: f64,
+    /// The number of orders at levels 1 and 2, or the order id at level 3
+    pub orders: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FillEstimate {
+    /// USD spent, which is less than asked for if the book runs out
+    pub funds: f64,
+    pub size: f64,
+    pub average_price: f64,
+    /// How far the average price is above the best ask, as a percentage
+    pub slippage: f64,
+}
+
+fn parse(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
+/// Add up the size of `entries` from the best price outward
+pub fn levels(entries: &[BookEntry]) -> Vec<Level> {
+    let mut cumulative = 0.0;
+    entries
+        .iter()
+        .map(|BookEntry(price, size, orders)| {
+            cumulative += parse(size);
+            Level {
+                price: price.clone(),
+                size: size.clone(),
+                cumulative,
+                orders: match orders {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+/// The gap between the best ask and the best bid, and as a percentage of the midpoint
+pub fn spread(book: &Book) -> Option<(f64, f64)> {
+    let bid = parse(&book.bids.first()?.0);
+    let ask = parse(&book.asks.first()?.0);
+    let mid = (bid + ask) / 2.0;
+    if mid <= 0.0 {
+        return None;
+    }
+    Some((ask - bid, (ask - bid) / mid * 100.0))
+}
+
+/// Estimate what a market buy of `funds` USD would get by walking up the asks, before fees
+pub fn estimate_buy(asks: &[BookEntry], funds: f64) -> Option<FillEstimate> {
+    let best = parse(&asks.first()?.0);
+    let mut spent = 0.0;
+    let mut size = 0.0;
+    for BookEntry(price, available, _) in asks {
+        let price = parse(price);
+        if price <= 0.0 || spent >= funds {
+            break;
+        }
+        let take = (funds - spent).min(price * parse(available));
+        spent += take;
+        size += take / price;
+    }
+    if size <= 0.0 {
+        return None;
+    }
+    let average_price = spent / size;
+    Some(FillEstimate {
+        funds: spent,
+        size,
+        average_price,
+        slippage: (average_price - best) / best * 100.0,
+    })
+}
+
+/// The best `depth` asks (worst at the top) and bids around the spread
+pub fn format_book(book: &Book, depth: usize) -> String {
+    let row = |side: &str, level: &Level| {
+        format!(
+            "{:<6} {:>14} {:>16} {:>16.8}  {}\n",
+            side, level.price, level.size, level.cumulative, level.orders
+        )
+    };
+    let mut out = format!(
+        "{:<6} {:>14} {:>16} {:>16}  {}\n",
+        "side", "price", "size", "cumulative", "orders"
+    );
+    for level in levels(&book.asks).iter().take(depth).rev() {
+        out.push_str(&row("ask", level));
+    }
+    if let Some((absolute, percent)) = spread(book) {
+        out.push_str(&format!(
+            "{:<6} {:>14.2} ({:.3}%)\n",
+            "spread", absolute, percent
+        ));
+    }
+    for level in levels(&book.bids).iter().take(depth) {
+        out.push_str(&row("bid", level));
+    }
+    out
+}
+
+pub fn format_estimate(estimate: &FillEstimate, funds: f64) -> String {
+    let mut line = format!(
+        "Buying ${:.2} would fill about {:.8} at an average price of {:.2}, {:.3}% above the best ask (before fees)",
+        funds, estimate.size, estimate.average_price, estimate.slippage
+    );
+    if estimate.funds < funds {
+        line.push_str(&format!(
+            "; the book fetched only covers ${:.2} of it",
+            estimate.funds
+        ));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests;
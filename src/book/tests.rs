@@ -0,0 +1,74 @@
+use super::*;
+use serde_json::json;
+
+fn book() -> Book {
+    serde_json::from_value(json!({
+        "sequence": 1,
+        "bids": [["99.00", "1.0", 1], ["98.00", "2.0", 3]],
+        "asks": [["101.00", "1.0", 2], ["102.00", "2.0", 1], ["104.00", "1.0", 1]],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_levels_and_spread() {
+    let book = book();
+    let asks = levels(&book.asks);
+    let cumulative: Vec<f64> = asks.iter().map(|l| l.cumulative).collect();
+    assert_eq!(cumulative, vec![1.0, 3.0, 4.0]);
+    assert_eq!(asks[0].orders, "2");
+
+    let (absolute, percent) = spread(&book).unwrap();
+    assert!((absolute - 2.0).abs() < 1e-9);
+    assert!((percent - 2.0).abs() < 1e-9);
+
+    let level3: Book = serde_json::from_value(json!({
+        "sequence": 1,
+        "bids": [["99.00", "1.0", "order-a"]],
+        "asks": [],
+    }))
+    .unwrap();
+    assert_eq!(levels(&level3.bids)[0].orders, "order-a");
+    assert_eq!(spread(&level3), None);
+}
+
+#[test]
+fn test_estimate_buy() {
+    let book = book();
+    // Within the best ask, there's no slippage
+    let small = estimate_buy(&book.asks, 50.5).unwrap();
+    assert!((small.size - 0.5).abs() < 1e-9);
+    assert!(small.slippage.abs() < 1e-9);
+
+    // $101 clears the best ask, and $204 more buys 2.0 at 102
+    let large = estimate_buy(&book.asks, 305.0).unwrap();
+    assert!((large.size - 3.0).abs() < 1e-9);
+    assert!((large.average_price - 305.0 / 3.0).abs() < 1e-9);
+    assert!((large.slippage - (305.0 / 3.0 - 101.0) / 101.0 * 100.0).abs() < 1e-9);
+
+    // More than the book holds spends what there is
+    let all = estimate_buy(&book.asks, 1000.0).unwrap();
+    assert!((all.funds - 409.0).abs() < 1e-9);
+    assert!(format_estimate(&all, 1000.0).ends_with("only covers $409.00 of it"));
+
+    assert_eq!(estimate_buy(&[], 10.0), None);
+}
+
+#[test]
+fn test_format_book() {
+    let lines: Vec<String> = format_book(&book(), 2)
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "side price size cumulative orders",
+            "ask 102.00 2.0 3.00000000 1",
+            "ask 101.00 1.0 1.00000000 2",
+            "spread 2.00 (2.000%)",
+            "bid 99.00 1.0 1.00000000 1",
+            "bid 98.00 2.0 3.00000000 3",
+        ]
+    );
+}
@@ -0,0 +1,386 @@
+use crate::api::{
+    get_accounts, list_transfers, make_deposit, order_fields, place_order, Order, Transfer,
+};
+use crate::config::Config;
+use crate::execution::{await_settled, maker_first_buy, ExecutionPath, MakerOptions};
+use crate::fees::print_fee_estimate;
+use crate::fiat::{check_amount, payment_method_id};
+use crate::ledger::{self, Kind};
+use crate::logging::{self, fields, ErrorKind};
+use crate::metrics;
+use crate::state::{self, QueuedBuy};
+use crate::strategy::{moving_average_amount, MovingAverageOptions};
+use crate::transfers;
+use chrono::Local;
+use std::str::FromStr;
+
+/// Exit code for a buy that was skipped because USD hadn't cleared yet
+pub static EXIT_INSUFFICIENT_FUNDS: i32 = 3;
+// Coinbase rejects market orders below its minimum funds; don't bother with dust
+static MIN_ORDER_FUNDS: f64 = 1.0;
+
+/// What to do when there isn't enough USD available for a buy
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsufficientFunds {
+    /// Don't buy anything
+    #[default]
+    Skip,
+    /// Buy with whatever is available
+    Partial,
+    /// Remember the buy and retry it on the next run
+    Queue,
+    /// Deposit the shortfall, and queue the buy until it clears
+    Deposit,
+}
+
+impl FromStr for InsufficientFunds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(InsufficientFunds::Skip),
+            "partial" => Ok(InsufficientFunds::Partial),
+            "queue" => Ok(InsufficientFunds::Queue),
+            "deposit" => Ok(InsufficientFunds::Deposit),
+            _ => Err(format!("'{}' is not an insufficient funds policy", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BuyOptions {
+    pub currency: String,
+    pub amount: f64,
+    pub strategy: Option<MovingAverageOptions>,
+    pub execution: Option<MakerOptions>,
+    pub on_insufficient_funds: InsufficientFunds,
+}
+
+#[derive(Debug)]
+pub enum BuyOutcome {
+    Bought(Vec<Order>),
+    /// Nothing was bought because there wasn't enough USD
+    Skipped,
+    /// Nothing was bought now; the buy will be retried on the next run
+    Queued,
+    /// A guardrail refused the deposit the buy needed
+    Refused(String),
+    Failed,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FundsPlan {
+    Buy(f64),
+    Skip,
+    Queue,
+    Deposit(f64),
+}
+
+/// Decide how much to buy given the USD available and the insufficient funds policy.
+/// `pending` is USD on its way from deposits that haven't cleared and that no queued
+/// buy is waiting on (see `pending_usd`), which only matters when the policy would
+/// deposit more
+pub fn plan_funds(
+    amount: f64,
+    available: f64,
+    pending: f64,
+    policy: InsufficientFunds,
+) -> FundsPlan {
+    if available >= amount {
+        return FundsPlan::Buy(amount);
+    }
+    match policy {
+        InsufficientFunds::Skip => FundsPlan::Skip,
+        InsufficientFunds::Partial => {
+            let funds = (available * 100.0).floor() / 100.0;
+            if funds >= MIN_ORDER_FUNDS {
+                FundsPlan::Buy(funds)
+            } else {
+                FundsPlan::Skip
+            }
+        }
+        InsufficientFunds::Queue => FundsPlan::Queue,
+        InsufficientFunds::Deposit => {
+            let shortfall = amount - available - pending;
+            if shortfall > 0.0 {
+                FundsPlan::Deposit((shortfall * 100.0).ceil() / 100.0)
+            } else {
+                FundsPlan::Queue
+            }
+        }
+    }
+}
+
+async fn available_usd() -> Option<f64> {
+    let accounts = get_accounts().await?;
+    Some(
+        accounts
+            .iter()
+            .find(|a| a.currency == "USD")
+            .and_then(|a| a.available.parse::<f64>().ok())
+            .unwrap_or(0.0),
+    )
+}
+
+/// USD from deposits that haven't cleared yet, less what the buys still queued are
+/// waiting on. ACH deposits take days, and without this every run in the meantime
+/// would deposit the same shortfall again. Negative when the queued buys also count
+/// on USD that is already available
+pub fn pending_usd(deposits: &[Transfer], queued: &[QueuedBuy]) -> f64 {
+    let in_flight: f64 = deposits
+        .iter()
+        .filter(|t| t.type_name == "deposit" && !transfers::status(t).is_final())
+        .filter_map(|t| t.amount.parse::<f64>().ok())
+        .sum();
+    let waiting: f64 = queued.iter().map(|q| q.amount).sum();
+    in_flight - waiting
+}
+
+/// Count what filled orders spent, fees included
+fn record_spent(orders: &[Order]) {
+    for o in orders {
+        let spent = o.executed_value.parse::<f64>().unwrap_or(0.0)
+            + o.fill_fees.parse::<f64>().unwrap_or(0.0);
+        metrics::inc(metrics::USD_SPENT, &[("product", &o.product_id)], spent);
+    }
+}
+
+fn queue_buy(currency: &str, amount: f64) -> bool {
+    let mut state = match state::load() {
+        Ok(s) => s,
+        Err(e) => {
+            logging::error(&e, fields().error(ErrorKind::Storage));
+            return false;
+        }
+    };
+    state.queued_buys.push(QueuedBuy {
+        currency: String::from(currency),
+        amount,
+        queued_at: Local::now().to_rfc3339(),
+    });
+    match state::save(&state) {
+        Ok(_) => {
+            logging::info(
+                "Queued for the next run",
+                fields()
+                    .product(&format!("{}-USD", currency))
+                    .amount(amount)
+                    .status("queued"),
+            );
+            true
+        }
+        Err(e) => {
+            logging::error(&e, fields().error(ErrorKind::Storage));
+            false
+        }
+    }
+}
+
+/// Place the buys queued by earlier runs for which there is now enough USD,
+/// returning the USD left available afterwards and the buys still queued
+async fn run_queued_buys(mut available: f64) -> (f64, Vec<QueuedBuy>) {
+    let mut state = match state::load() {
+        Ok(s) => s,
+        Err(e) => {
+            logging::error(&e, fields().error(ErrorKind::Storage));
+            return (available, Vec::new());
+        }
+    };
+    if state.queued_buys.is_empty() {
+        return (available, Vec::new());
+    }
+
+    let mut still_queued = Vec::new();
+    for queued in state.queued_buys.drain(..) {
+        if queued.amount > available {
+            still_queued.push(queued);
+            continue;
+        }
+        logging::info(
+            &format!("Purchasing a buy queued at {}", queued.queued_at),
+            fields()
+                .product(&format!("{}-USD", queued.currency))
+                .amount(queued.amount),
+        );
+        match place_order(&queued.amount, &queued.currency).await {
+            Some(r) => {
+                ledger::record(ledger::order_entry(&r));
+                let r = await_settled(r).await;
+                logging::info("Purchase successful", order_fields(&r));
+                println!("{:#?}", r);
+                logging::debug(&format!("{:#?}", r), order_fields(&r));
+                record_spent(std::slice::from_ref(&r));
+                available -= queued.amount;
+            }
+            None => still_queued.push(queued),
+        }
+    }
+    state.queued_buys = still_queued;
+    if let Err(e) = state::save(&state) {
+        logging::error(&e, fields().error(ErrorKind::Storage));
+    }
+    (available, state.queued_buys)
+}
+
+/// Buy `options.amount` USD worth of `options.currency`: scale the amount with the
+/// strategy, make sure the USD is there, then place the order(s)
+pub async fn buy(options: &BuyOptions, config: &Config) -> BuyOutcome {
+    let product_id = format!("{}-USD", options.currency);
+    let amount = match &options.strategy {
+        Some(strategy) => {
+            match moving_average_amount(options.amount, &product_id, strategy).await {
+                Some(scaled) => {
+                    logging::info(
+                        &scaled.explanation,
+                        fields().product(&product_id).amount(scaled.amount),
+                    );
+                    scaled.amount
+                }
+                None => return BuyOutcome::Failed,
+            }
+        }
+        None => options.amount,
+    };
+
+    let (available, queued) = match available_usd().await {
+        Some(a) => run_queued_buys(a).await,
+        None => return BuyOutcome::Failed,
+    };
+    let policy = options.on_insufficient_funds;
+    let pending = if policy == InsufficientFunds::Deposit && available < amount {
+        match list_transfers(Some("deposit")).await {
+            Some(deposits) => pending_usd(&deposits, &queued),
+            None => return BuyOutcome::Failed,
+        }
+    } else {
+        0.0
+    };
+    let amount = match plan_funds(amount, available, pending, policy) {
+        FundsPlan::Buy(a) => {
+            if a < amount {
+                logging::warn(
+                    &format!("Only ${} USD is available; buying with that", a),
+                    fields().product(&product_id).amount(a),
+                );
+            }
+            a
+        }
+        FundsPlan::Skip => {
+            logging::error(
+                &format!(
+                    "Insufficient funds: ${} USD available, ${} needed; skipping",
+                    available, amount
+                ),
+                fields()
+                    .product(&product_id)
+                    .amount(amount)
+                    .status("skipped")
+                    .error(ErrorKind::Refused),
+            );
+            return BuyOutcome::Skipped;
+        }
+        FundsPlan::Queue => {
+            let covered = if pending > 0.0 {
+                format!(
+                    "; ${} USD in deposits that haven't cleared covers it",
+                    pending
+                )
+            } else {
+                String::new()
+            };
+            logging::warn(
+                &format!(
+                    "Insufficient funds: ${} USD available, ${} needed{}",
+                    available, amount, covered
+                ),
+                fields().product(&product_id).amount(amount),
+            );
+            return if queue_buy(&options.currency, amount) {
+                BuyOutcome::Queued
+            } else {
+                BuyOutcome::Failed
+            };
+        }
+        FundsPlan::Deposit(shortfall) => {
+            logging::warn(
+                &format!(
+                    "Insufficient funds: ${} USD available, ${} needed; depositing ${}",
+                    available, amount, shortfall
+                ),
+                fields().product(&product_id).amount(amount),
+            );
+            if let Err(e) = check_amount(shortfall, config.guardrails.max_deposit, "deposit") {
+                logging::error(&e, fields().amount(shortfall).error(ErrorKind::Refused));
+                return BuyOutcome::Refused(e);
+            }
+            let bank_id = match payment_method_id(None, false).await {
+                Ok(id) => id,
+                Err(e) => {
+                    logging::error(&e, fields().error(ErrorKind::Storage));
+                    return BuyOutcome::Failed;
+                }
+            };
+            match make_deposit(&shortfall, &bank_id).await {
+                Some(r) => ledger::record(ledger::deposit_entry(Kind::Deposit, &r)),
+                None => return BuyOutcome::Failed,
+            }
+            return if queue_buy(&options.currency, amount) {
+                BuyOutcome::Queued
+            } else {
+                BuyOutcome::Failed
+            };
+        }
+    };
+
+    // The estimate is informational; a failure to fetch it shouldn't stop the buy
+    let fees = print_fee_estimate(amount, options.execution.is_some()).await;
+
+    if let Some(maker) = &options.execution {
+        logging::info(
+            "Purchasing maker-first",
+            fields().product(&product_id).amount(amount),
+        );
+        return match maker_first_buy(amount, &product_id, maker, fees).await {
+            Some(r) => {
+                let status = format!("{:?}", r.path).to_lowercase();
+                logging::info(
+                    &format!(
+                        "Executed via {:?}: maker ${:.2} filled with ${:.4} in fees, taker ${:.2} filled with ~${:.4} in fees; saved ~${:.4} versus a market order",
+                        r.path, r.maker_value, r.maker_fees, r.taker_value, r.taker_fees, r.fee_saved
+                    ),
+                    fields().product(&product_id).amount(amount).status(&status),
+                );
+                println!("{:#?}", r.orders);
+                logging::debug(&format!("{:#?}", r.orders), fields().product(&product_id));
+                record_spent(&r.orders);
+                if r.path == ExecutionPath::GaveUp {
+                    BuyOutcome::Failed
+                } else {
+                    BuyOutcome::Bought(r.orders)
+                }
+            }
+            None => BuyOutcome::Failed,
+        };
+    }
+
+    logging::info(
+        "Purchasing at market",
+        fields().product(&product_id).amount(amount),
+    );
+    match place_order(&amount, &options.currency).await {
+        Some(r) => {
+            ledger::record(ledger::order_entry(&r));
+            let r = await_settled(r).await;
+            logging::info("Purchase successful", order_fields(&r));
+            println!("{:#?}", r);
+            logging::debug(&format!("{:#?}", r), order_fields(&r));
+            record_spent(std::slice::from_ref(&r));
+            BuyOutcome::Bought(vec![r])
+        }
+        None => BuyOutcome::Failed,
+    }
+}
+
+#[cfg(test)]
+mod tests;
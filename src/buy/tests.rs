@@ -0,0 +1,85 @@
+use super::*;
+
+#[test]
+fn test_plan_funds() {
+    use InsufficientFunds::*;
+
+    // Enough USD always buys the full amount
+    assert_eq!(plan_funds(10.0, 10.0, 0.0, Skip), FundsPlan::Buy(10.0));
+    assert_eq!(plan_funds(10.0, 50.0, 0.0, Queue), FundsPlan::Buy(10.0));
+
+    assert_eq!(plan_funds(10.0, 4.567, 0.0, Skip), FundsPlan::Skip);
+    assert_eq!(plan_funds(10.0, 4.567, 0.0, Partial), FundsPlan::Buy(4.56));
+    assert_eq!(plan_funds(10.0, 0.5, 0.0, Partial), FundsPlan::Skip);
+    assert_eq!(plan_funds(10.0, 4.567, 0.0, Queue), FundsPlan::Queue);
+    assert_eq!(
+        plan_funds(10.0, 4.567, 0.0, Deposit),
+        FundsPlan::Deposit(5.44)
+    );
+}
+
+fn deposit(amount: &str, completed: bool) -> Transfer {
+    serde_json::from_value(serde_json::json!({
+        "id": "19ac524d-8827-4246-a1b2-18dc5ca9472c",
+        "type": "deposit",
+        "created_at": "2020-03-12 00:14:12+00",
+        "completed_at": if completed { Some("2020-03-15 00:14:12+00") } else { None },
+        "amount": amount,
+    }))
+    .unwrap()
+}
+
+fn queued(currency: &str, amount: f64) -> QueuedBuy {
+    QueuedBuy {
+        currency: String::from(currency),
+        amount,
+        queued_at: String::from("2020-03-12T00:14:12+00:00"),
+    }
+}
+
+#[test]
+fn test_pending_deposit_covers_shortfall() {
+    let deposits = vec![deposit("10.00", false), deposit("25.00", true)];
+    assert_eq!(pending_usd(&deposits, &[]), 10.0);
+
+    // The deposit from an earlier run covers this one, so nothing new is deposited
+    let pending = pending_usd(&deposits, &[]);
+    assert_eq!(
+        plan_funds(10.0, 4.0, pending, InsufficientFunds::Deposit),
+        FundsPlan::Queue
+    );
+    // Only the part it doesn't cover is deposited
+    assert_eq!(
+        plan_funds(20.0, 4.0, pending, InsufficientFunds::Deposit),
+        FundsPlan::Deposit(6.0)
+    );
+
+    // A deposit a queued buy is waiting on isn't counted twice
+    let pending = pending_usd(&deposits, &[queued("ETH", 8.0)]);
+    assert_eq!(pending, 2.0);
+    assert_eq!(
+        plan_funds(10.0, 4.0, pending, InsufficientFunds::Deposit),
+        FundsPlan::Deposit(4.0)
+    );
+}
+
+#[test]
+fn test_buy_while_another_waits_on_a_deposit() {
+    // $4 was available for a $10 buy, so $6 was deposited and the buy queued
+    let deposits = vec![deposit("6.00", false)];
+    let waiting = vec![queued("BTC", 10.0)];
+    let pending = pending_usd(&deposits, &waiting);
+    assert_eq!(pending, -4.0);
+
+    // The next buy can't count on the $4 too, so it's queued with a deposit of its own
+    assert_eq!(
+        plan_funds(10.0, 4.0, pending, InsufficientFunds::Deposit),
+        FundsPlan::Deposit(10.0)
+    );
+}
+
+#[test]
+fn test_parse_policy() {
+    assert_eq!("partial".parse(), Ok(InsufficientFunds::Partial));
+    assert!("sometimes".parse::<InsufficientFunds>().is_err());
+}
@@ -0,0 +1,169 @@
+use crate::buy::InsufficientFunds;
+use crate::daemon::CatchUp;
+use crate::logging::LogSettings;
+use crate::notify::NotifySettings;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Settings that don't fit on the command line, read from `$HODL_CONFIG`
+/// or `~/.hodl/config.toml`. A missing file is the same as an empty one.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// Withdrawal destinations by name; coins can only be sent to these
+    #[serde(default)]
+    pub addresses: HashMap<String, Address>,
+    /// Per-currency rules for `sweep`, keyed by currency (ex: BTC)
+    #[serde(default)]
+    pub sweep: HashMap<String, SweepRule>,
+    #[serde(default)]
+    pub guardrails: Guardrails,
+    #[serde(default)]
+    pub buy: BuySettings,
+    /// Jobs for `hodl daemon` to run
+    #[serde(default)]
+    pub schedule: Vec<ScheduleEntry>,
+    /// Where to send notifications of job outcomes
+    #[serde(default)]
+    pub notify: NotifySettings,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub log: LogSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleEntry {
+    /// Identifies the job in logs; defaults to a description of the job
+    pub name: Option<String>,
+    /// A cron expression, or "every N days at HH:MM"
+    pub when: String,
+    /// What to do about runs missed while hodl wasn't running
+    #[serde(default)]
+    pub catch_up: CatchUp,
+    /// The most missed runs to make up at once with `catch_up = "all"`
+    pub max_catch_up: Option<u32>,
+    #[serde(flatten)]
+    pub job: Job,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "job", rename_all = "lowercase")]
+pub enum Job {
+    Buy { currency: String, amount: f64 },
+    Deposit { amount: f64 },
+    Sweep,
+    Report { account_id: String, email: String },
+}
+
+impl ScheduleEntry {
+    pub fn name(&self) -> String {
+        if let Some(n) = &self.name {
+            return n.clone();
+        }
+        match &self.job {
+            Job::Buy { currency, amount } => format!("buy {} {}", currency, amount),
+            Job::Deposit { amount } => format!("deposit {}", amount),
+            Job::Sweep => String::from("sweep"),
+            Job::Report { account_id, .. } => format!("report {}", account_id),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BuySettings {
+    /// Policy for buys without enough available USD; see `--on-insufficient-funds`
+    #[serde(default)]
+    pub on_insufficient_funds: InsufficientFunds,
+}
+
+/// Where to export Prometheus metrics
+#[derive(Debug, Default, Deserialize)]
+pub struct MetricsSettings {
+    /// Address for `hodl daemon` to serve `/metrics` on (ex: 127.0.0.1:9184)
+    pub listen: Option<String>,
+    /// node_exporter textfile to update after each command
+    pub textfile: Option<PathBuf>,
+}
+
+/// Upper bounds on how much money a single command may move
+#[derive(Debug, Default, Deserialize)]
+pub struct Guardrails {
+    pub max_deposit: Option<f64>,
+    pub max_fiat_withdrawal: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Address {
+    pub currency: String,
+    pub address: String,
+    pub destination_tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SweepRule {
+    /// Sweep once the available balance exceeds this amount
+    pub threshold: f64,
+    /// Amount to leave on the exchange after sweeping
+    #[serde(default)]
+    pub retain: f64,
+    /// Name of the address book entry to sweep to
+    pub destination: String,
+    /// Skip the sweep (and let the balance grow) while the network fee is
+    /// a larger percentage of the amount than this
+    pub max_fee_percent: Option<f64>,
+}
+
+/// Directory where hodl keeps its config and state; `$HODL_HOME` or `~/.hodl`
+pub fn hodl_home() -> PathBuf {
+    match env::var("HODL_HOME") {
+        Ok(h) => PathBuf::from(h),
+        Err(_) => {
+            PathBuf::from(env::var("HOME").unwrap_or_else(|_| String::from("."))).join(".hodl")
+        }
+    }
+}
+
+/// The API key set hodl is running as; `$HODL_PROFILE` or "default". Invocations
+/// with the same profile share a lock and a rate limit budget
+pub fn profile() -> String {
+    let profile = env::var("HODL_PROFILE").unwrap_or_default();
+    if profile.is_empty() {
+        return String::from("default");
+    }
+    // It names files in hodl_home, so keep it to a plain file name
+    profile
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn config_path() -> PathBuf {
+    match env::var("HODL_CONFIG") {
+        Ok(p) => PathBuf::from(p),
+        Err(_) => hodl_home().join("config.toml"),
+    }
+}
+
+pub fn parse(contents: &str) -> Result<Config, String> {
+    toml::from_str(contents).map_err(|e| e.to_string())
+}
+
+pub fn load() -> Result<Config, String> {
+    let path = config_path();
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse(&contents).map_err(|e| format!("Invalid config in {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,72 @@
+use super::*;
+
+#[test]
+fn test_parse_addresses() {
+    let config = parse(
+        r#"
+[addresses.cold-btc]
+currency = "BTC"
+address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+
+[addresses.ledger-xrp]
+currency = "XRP"
+address = "rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY"
+destination_tag = "12345"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.addresses.len(), 2);
+    assert_eq!(config.addresses["cold-btc"].currency, "BTC");
+    assert_eq!(config.addresses["cold-btc"].destination_tag, None);
+    assert_eq!(
+        config.addresses["ledger-xrp"].destination_tag,
+        Some(String::from("12345"))
+    );
+    assert!(parse("").unwrap().addresses.is_empty());
+}
+
+#[test]
+fn test_parse_schedule() {
+    let config = parse(
+        r#"
+[[schedule]]
+job = "deposit"
+when = "every 3 days at 00:00"
+amount = 39.0
+
+[[schedule]]
+name = "daily-btc"
+job = "buy"
+when = "7 0 * * *"
+currency = "BTC"
+amount = 8.0
+catch_up = "all"
+max_catch_up = 5
+
+[[schedule]]
+job = "sweep"
+when = "0 1 * * 0"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.schedule.len(), 3);
+    assert_eq!(config.schedule[0].job, Job::Deposit { amount: 39.0 });
+    assert_eq!(config.schedule[0].name(), "deposit 39");
+    assert_eq!(config.schedule[1].name(), "daily-btc");
+    assert_eq!(
+        config.schedule[1].job,
+        Job::Buy {
+            currency: String::from("BTC"),
+            amount: 8.0
+        }
+    );
+    assert_eq!(config.schedule[1].catch_up, CatchUp::All);
+    assert_eq!(config.schedule[1].max_catch_up, Some(5));
+    assert_eq!(config.schedule[2].job, Job::Sweep);
+    assert_eq!(config.schedule[2].catch_up, CatchUp::Skip);
+
+    // A buy without an amount is caught when the config is loaded
+    assert!(
+        parse("[[schedule]]\njob = \"buy\"\nwhen = \"* * * * *\"\ncurrency = \"BTC\"").is_err()
+    );
+}
@@ -0,0 +1,366 @@
+use crate::api::{make_deposit, request_report};
+use crate::buy::{buy, BuyOptions, BuyOutcome};
+use crate::config::{Config, Job, ScheduleEntry};
+use crate::fiat::{check_amount, payment_method_id};
+use crate::ledger::{self, Kind};
+use crate::lock;
+use crate::logging::{self, fields};
+use crate::metrics;
+use crate::notify::{check_balances, notify, summarize_orders, Event, EventKind};
+use crate::schedule::{self, Schedule};
+use crate::state;
+use crate::sweep::sweep;
+use chrono::{DateTime, Duration, Local};
+use std::env;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::sleep;
+
+// Wake up at least this often so a changed clock (ex: NTP sync on a Pi that
+// booted without an RTC) doesn't leave a job sleeping far past its time
+static MAX_SLEEP_SECS: i64 = 60;
+// Used when a job with `catch_up = "all"` doesn't set `max_catch_up`
+static DEFAULT_MAX_CATCH_UP: u32 = 3;
+// Stop counting missed runs here; a minutely job down for a month doesn't need an exact tally
+static MAX_MISSED_COUNTED: usize = 1000;
+
+/// What to do about the runs of a scheduled job that were missed while hodl wasn't running
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    /// Log the missed runs, but don't make them up
+    #[default]
+    Skip,
+    /// Run the job once, however many runs were missed
+    Once,
+    /// Run the job once per missed run, up to `max_catch_up`
+    All,
+}
+
+/// How many times to run a job to make up for `missed` runs
+pub fn catch_up_count(policy: CatchUp, missed: usize, max: Option<u32>) -> usize {
+    match policy {
+        CatchUp::Skip => 0,
+        CatchUp::Once => missed.min(1),
+        CatchUp::All => missed.min(max.unwrap_or(DEFAULT_MAX_CATCH_UP) as usize),
+    }
+}
+
+struct ScheduledJob {
+    entry: ScheduleEntry,
+    schedule: Schedule,
+    next_run: Option<DateTime<Local>>,
+}
+
+// The jobs' own events carry their product, amount and so on; the daemon's are about the jobs
+fn log(message: &str) {
+    logging::info(message, fields());
+}
+
+fn log_error(message: &str) {
+    logging::error(message, fields());
+}
+
+/// Parse every schedule in the config, so a typo fails at startup instead of
+/// silently never running
+fn parse_schedules(config: &Config, now: DateTime<Local>) -> Result<Vec<ScheduledJob>, String> {
+    config
+        .schedule
+        .iter()
+        .map(|entry| {
+            let schedule = schedule::parse(&entry.when)
+                .map_err(|e| format!("Invalid schedule for '{}': {}", entry.name(), e))?;
+            let next_run = schedule.next_after(now);
+            Ok(ScheduledJob {
+                entry: entry.clone(),
+                schedule,
+                next_run,
+            })
+        })
+        .collect()
+}
+
+/// Run a job, describing how it went as the event to notify of
+async fn run_job(name: &str, job: &Job, config: &Config) -> Event {
+    let success = |message: String| Event::new(EventKind::Success, name, &message);
+    let failure = |message: &str| Event::new(EventKind::Failure, name, message);
+    match job {
+        Job::Buy { currency, amount } => {
+            let options = BuyOptions {
+                currency: currency.clone(),
+                amount: *amount,
+                strategy: None,
+                execution: None,
+                on_insufficient_funds: config.buy.on_insufficient_funds,
+            };
+            match buy(&options, config).await {
+                BuyOutcome::Bought(orders) => Event {
+                    summary: Some(summarize_orders(&orders)),
+                    ..success(format!("placed {} order(s)", orders.len()))
+                },
+                BuyOutcome::Queued => success(String::from("queued for the next run")),
+                BuyOutcome::Skipped => failure("skipped for insufficient funds"),
+                BuyOutcome::Refused(e) => Event::new(EventKind::Refusal, name, &e),
+                BuyOutcome::Failed => failure("buy failed"),
+            }
+        }
+        Job::Deposit { amount } => {
+            if let Err(e) = check_amount(*amount, config.guardrails.max_deposit, "deposit") {
+                return Event::new(EventKind::Refusal, name, &e);
+            }
+            let bank_id = match payment_method_id(None, false).await {
+                Ok(id) => id,
+                Err(e) => return failure(&e),
+            };
+            match make_deposit(amount, &bank_id).await {
+                Some(r) => {
+                    ledger::record(ledger::deposit_entry(Kind::Deposit, &r));
+                    success(format!("deposit {} of ${} initiated", r.id, r.amount))
+                }
+                None => failure("deposit failed"),
+            }
+        }
+        Job::Sweep => {
+            if sweep(config, false).await {
+                success(String::from("sweep complete"))
+            } else {
+                failure("sweep failed")
+            }
+        }
+        Job::Report { account_id, email } => {
+            let end = Local::now();
+            let start = end - Duration::weeks(4);
+            match request_report(&start.to_string(), &end.to_string(), account_id, email).await {
+                Some(r) => success(format!("report requested: {:?}", r)),
+                None => failure("report request failed"),
+            }
+        }
+    }
+}
+
+/// Run a job on its own task, so a panic is logged instead of taking down the daemon,
+/// holding the profile's lock while it runs and, if it succeeds, while the run due at
+/// `record_at` is remembered. Then notify of the outcome and any low balances.
+/// Returns whether the job succeeded.
+async fn run_logged(
+    entry: &ScheduleEntry,
+    config: &Arc<Config>,
+    record_at: Option<DateTime<Local>>,
+) -> bool {
+    let name = entry.name();
+    log(&format!("Running '{}'", name));
+    let job = entry.job.clone();
+    let task_name = name.clone();
+    let task_config = Arc::clone(config);
+    let task = tokio::spawn(async move {
+        // Take turns with hodl commands run from cron; released even if the job panics
+        let _lock = match lock::acquire(&format!("hodl daemon: {}", task_name)).await {
+            Ok(l) => l,
+            Err(e) => return Event::new(EventKind::Failure, &task_name, &e),
+        };
+        let event = run_job(&task_name, &job, &task_config).await;
+        if event.kind == EventKind::Success {
+            if let Some(at) = record_at {
+                record_run(&task_name, at);
+            }
+        }
+        event
+    });
+    let event = match task.await {
+        Ok(event) => event,
+        Err(e) => Event::new(EventKind::Failure, &name, &format!("panicked: {}", e)),
+    };
+    match event.kind {
+        EventKind::Success => log(&format!("'{}' succeeded: {}", name, event.message)),
+        _ => log_error(&format!(
+            "'{}' {}: {}",
+            name,
+            event.kind.as_str(),
+            event.message
+        )),
+    }
+    let success = event.kind == EventKind::Success;
+    metrics::record_run(&name, success);
+    notify(&config.notify, &event).await;
+    check_balances(&config.notify, &name).await;
+    success
+}
+
+/// Remember that the run of `name` scheduled for `at` is done. Only call this while
+/// holding the profile's lock, as the state is shared with queued buys.
+fn record_run(name: &str, at: DateTime<Local>) {
+    let result = state::load().and_then(|mut s| {
+        s.last_runs.insert(String::from(name), at.to_rfc3339());
+        state::save(&s)
+    });
+    if let Err(e) = result {
+        log_error(&e);
+    }
+}
+
+/// `record_run` without a job to run, taking the profile's lock for it
+async fn record_run_locked(name: &str, at: DateTime<Local>) {
+    match lock::acquire(&format!("hodl daemon: {}", name)).await {
+        Ok(_lock) => record_run(name, at),
+        Err(e) => log_error(&e),
+    }
+}
+
+fn last_run(name: &str) -> Result<Option<DateTime<Local>>, String> {
+    let state = state::load()?;
+    match state.last_runs.get(name) {
+        Some(t) => DateTime::parse_from_rfc3339(t)
+            .map(|t| Some(t.with_timezone(&Local)))
+            .map_err(|e| format!("Invalid last run '{}' for '{}': {}", t, name, e)),
+        None => Ok(None),
+    }
+}
+
+/// Find the runs missed since each job last ran and make them up according to its
+/// `catch_up` policy. Returns whether every run made up succeeded
+async fn catch_up_jobs(jobs: &[ScheduledJob], config: &Arc<Config>, now: DateTime<Local>) -> bool {
+    let mut ok = true;
+    for job in jobs {
+        let name = job.entry.name();
+        let since = match last_run(&name) {
+            Ok(Some(t)) => t,
+            // Nothing to catch up on before the first run; start tracking from now
+            Ok(None) => {
+                record_run_locked(&name, now).await;
+                continue;
+            }
+            Err(e) => {
+                log_error(&e);
+                ok = false;
+                continue;
+            }
+        };
+        let missed = job.schedule.missed_runs(since, now, MAX_MISSED_COUNTED);
+        let latest = match missed.last() {
+            Some(t) => *t,
+            None => continue,
+        };
+        let runs = catch_up_count(job.entry.catch_up, missed.len(), job.entry.max_catch_up);
+        log(&format!(
+            "'{}' missed {}{} run(s) since {}; catching up with {} run(s)",
+            name,
+            missed.len(),
+            if missed.len() == MAX_MISSED_COUNTED {
+                "+"
+            } else {
+                ""
+            },
+            since,
+            runs
+        ));
+        // A failed catch-up is retried next time, so only the last run records that
+        // the missed runs are done
+        let mut succeeded = true;
+        for i in 0..runs {
+            let record_at = if i + 1 == runs { Some(latest) } else { None };
+            if !run_logged(&job.entry, config, record_at).await {
+                succeeded = false;
+                break;
+            }
+        }
+        if runs == 0 {
+            record_run_locked(&name, latest).await;
+        }
+        ok &= succeeded;
+    }
+    ok
+}
+
+/// Make up the runs of scheduled jobs missed while hodl wasn't running, without
+/// starting the daemon. Returns whether every run made up succeeded
+pub async fn catch_up(config: Config) -> Result<bool, String> {
+    check_env()?;
+    let now = Local::now();
+    let jobs = parse_schedules(&config, now)?;
+    Ok(catch_up_jobs(&jobs, &Arc::new(config), now).await)
+}
+
+fn check_env() -> Result<(), String> {
+    for var in &[
+        "COINBASE_API_KEY",
+        "COINBASE_API_SECRET",
+        "COINBASE_API_PASSPHRASE",
+    ] {
+        if env::var(var).is_err() {
+            return Err(format!(
+                "Set the {} environment variable to run scheduled jobs",
+                var
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Catch up on missed runs, then run the jobs in `config.schedule` until SIGTERM or
+/// Ctrl-C. A job that is already running when the signal arrives is allowed to finish first
+pub async fn run(config: Config) -> Result<(), String> {
+    check_env()?;
+    let now = Local::now();
+    let mut jobs = parse_schedules(&config, now)?;
+    if jobs.is_empty() {
+        return Err(String::from(
+            "No jobs to run; add [[schedule]] entries to your config",
+        ));
+    }
+    if let Some(addr) = &config.metrics.listen {
+        let bound = metrics::serve(addr).await?;
+        log(&format!("Serving metrics on http://{}/metrics", bound));
+    }
+    let config = Arc::new(config);
+    // Registered up front so a signal that arrives mid-job is seen once it finishes
+    let mut terminate =
+        signal(SignalKind::terminate()).map_err(|e| format!("Unable to handle SIGTERM: {}", e))?;
+
+    catch_up_jobs(&jobs, &config, now).await;
+    for job in &jobs {
+        match job.next_run {
+            Some(t) => log(&format!("'{}' next runs at {}", job.entry.name(), t)),
+            None => log(&format!("'{}' will never run", job.entry.name())),
+        }
+    }
+
+    loop {
+        let now = Local::now();
+        for job in jobs.iter_mut() {
+            let due = match job.next_run {
+                Some(t) if t <= now => t,
+                _ => continue,
+            };
+            run_logged(&job.entry, &config, Some(due)).await;
+            job.next_run = job.schedule.next_after(Local::now());
+            if let Some(t) = job.next_run {
+                log(&format!("'{}' next runs at {}", job.entry.name(), t));
+            }
+        }
+
+        let wait = jobs
+            .iter()
+            .filter_map(|j| j.next_run)
+            .min()
+            .map(|t| {
+                (t - Local::now())
+                    .num_milliseconds()
+                    .clamp(0, MAX_SLEEP_SECS * 1000)
+            })
+            .unwrap_or(MAX_SLEEP_SECS * 1000);
+        tokio::select! {
+            _ = sleep(std::time::Duration::from_millis(wait as u64)) => {}
+            _ = terminate.recv() => {
+                log("Received SIGTERM, shutting down");
+                return Ok(());
+            }
+            _ = tokio::signal::ctrl_c() => {
+                log("Interrupted, shutting down");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,11 @@
+use super::*;
+
+#[test]
+fn test_catch_up_count() {
+    assert_eq!(catch_up_count(CatchUp::Skip, 5, None), 0);
+    assert_eq!(catch_up_count(CatchUp::Once, 5, None), 1);
+    assert_eq!(catch_up_count(CatchUp::Once, 0, None), 0);
+    assert_eq!(catch_up_count(CatchUp::All, 2, None), 2);
+    assert_eq!(catch_up_count(CatchUp::All, 10, None), 3);
+    assert_eq!(catch_up_count(CatchUp::All, 10, Some(7)), 7);
+}
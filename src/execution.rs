@@ -0,0 +1,230 @@
+use crate::api::{
+    cancel_order, get_fees, get_order, get_product, get_tick, order_fields, place_limit_order,
+    place_order, Fees, Order,
+};
+use crate::ledger::{self, Kind};
+use crate::logging::{self, fields};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+static POLL_INTERVAL_SECS: u64 = 2;
+// Market orders settle within seconds; don't hold up a run for longer than this
+static SETTLE_TIMEOUT_SECS: u64 = 30;
+// Anything smaller than a cent isn't worth chasing with another order
+static MIN_REMAINING_FUNDS: f64 = 0.01;
+pub static DEFAULT_ATTEMPTS: &str = "3";
+pub static DEFAULT_REPRICE_SECS: &str = "30";
+pub static DEFAULT_DEADLINE_SECS: &str = "300";
+
+#[derive(Debug, PartialEq)]
+pub enum Fallback {
+    Market,
+    GiveUp,
+}
+
+#[derive(Debug)]
+pub struct MakerOptions {
+    pub attempts: u32,
+    pub reprice_secs: u64,
+    pub deadline_secs: u64,
+    pub fallback: Fallback,
+}
+
+/// Which route(s) ended up filling a maker-first buy
+#[derive(Debug, PartialEq)]
+pub enum ExecutionPath {
+    Maker,
+    MakerThenMarket,
+    Market,
+    GaveUp,
+}
+
+#[derive(Debug)]
+pub struct MakerResult {
+    pub path: ExecutionPath,
+    pub orders: Vec<Order>,
+    pub maker_value: f64,
+    pub maker_fees: f64,
+    pub taker_value: f64,
+    pub taker_fees: f64,
+    pub fee_saved: f64,
+}
+
+/// Round `value` down to a multiple of `increment` (ex: "0.00000001"),
+/// formatted with as many decimals as the increment has
+pub fn floor_to_increment(value: f64, increment: &str) -> String {
+    let decimals = increment
+        .split('.')
+        .nth(1)
+        .map(|d| d.trim_end_matches('0').len())
+        .unwrap_or(0);
+    let step = increment.parse::<f64>().unwrap_or(1.0);
+    // Nudge by a tiny epsilon so 0.3 / 0.1 doesn't floor to 2
+    let floored = ((value / step) + 1e-9).floor() * step;
+    format!("{:.*}", decimals, floored)
+}
+
+/// Fees saved by filling `maker_value` as a maker instead of paying the taker rate
+pub fn fee_saved(maker_value: f64, maker_fees: f64, taker_rate: f64) -> f64 {
+    maker_value * taker_rate - maker_fees
+}
+
+fn parse(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
+/// Wait for `order` to fill, giving up once the reprice interval or deadline passes
+/// or the best bid moves above our price. Returns the order as last seen.
+async fn await_fill(order: Order, reprice_at: Instant, deadline: Instant) -> Order {
+    let mut latest = order;
+    let our_price = parse(latest.price.as_deref().unwrap_or("0"));
+    while Instant::now() < reprice_at && Instant::now() < deadline {
+        sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        match get_order(&latest.id).await {
+            Some(o) => latest = o,
+            None => break,
+        }
+        if latest.status == "done" {
+            break;
+        }
+        if let Some(tick) = get_tick(&latest.product_id).await {
+            if parse(&tick.bid) > our_price {
+                logging::info(
+                    &format!(
+                        "Best bid moved to {} above our {}; re-pricing",
+                        tick.bid, our_price
+                    ),
+                    order_fields(&latest),
+                );
+                break;
+            }
+        }
+    }
+    latest
+}
+
+/// Wait for a market order to settle and record its final fills in the ledger.
+/// Returns the order as last seen.
+pub async fn await_settled(order: Order) -> Order {
+    let deadline = Instant::now() + Duration::from_secs(SETTLE_TIMEOUT_SECS);
+    let mut latest = order;
+    while !latest.settled && Instant::now() < deadline {
+        sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        match get_order(&latest.id).await {
+            Some(o) => latest = o,
+            None => break,
+        }
+    }
+    ledger::record_update(ledger::order_entry(&latest));
+    latest
+}
+
+/// Buy `amount` USD of `product_id` with post-only limit orders at the best bid,
+/// re-pricing up to `attempts` times, then fall back to a market order (or give up).
+/// `fees` are the current rates if the caller has them already
+pub async fn maker_first_buy(
+    amount: f64,
+    product_id: &str,
+    options: &MakerOptions,
+    fees: Option<Fees>,
+) -> Option<MakerResult> {
+    let product = get_product(product_id).await?;
+    let fees = match fees {
+        Some(f) => f,
+        None => get_fees().await?,
+    };
+    let maker_rate = parse(&fees.maker_fee_rate);
+    let taker_rate = parse(&fees.taker_fee_rate);
+
+    let deadline = Instant::now() + Duration::from_secs(options.deadline_secs);
+    let mut orders = Vec::new();
+    let mut maker_value = 0.0;
+    let mut maker_fees = 0.0;
+
+    for attempt in 1..=options.attempts {
+        let remaining = amount - maker_value - maker_fees;
+        if remaining < MIN_REMAINING_FUNDS || Instant::now() >= deadline {
+            break;
+        }
+        let tick = get_tick(product_id).await?;
+        let price = floor_to_increment(parse(&tick.bid), &product.quote_increment);
+        // Leave room for the maker fee so the order never needs more than we have
+        let size = floor_to_increment(
+            remaining / (parse(&price) * (1.0 + maker_rate)),
+            &product.base_increment,
+        );
+        if parse(&size) <= 0.0 {
+            break;
+        }
+
+        logging::info(
+            &format!(
+                "Attempt {}/{}: post-only bid for {} {} at {}",
+                attempt, options.attempts, size, product.base_currency, price
+            ),
+            fields().product(product_id).amount(&size),
+        );
+        let order = match place_limit_order(product_id, "buy", &price, &size, true).await {
+            Some(o) => o,
+            None => continue,
+        };
+        ledger::record(ledger::order_entry(&order));
+        let reprice_at = Instant::now() + Duration::from_secs(options.reprice_secs);
+        let mut order = await_fill(order, reprice_at, deadline).await;
+        if order.status == "done" {
+            ledger::record_update(ledger::order_entry(&order));
+        } else {
+            if cancel_order(&order.id).await {
+                ledger::record_status(Kind::Order, &order.id, "canceled");
+            }
+            // A cancelled order with no fills disappears from the API
+            if let Some(o) = get_order(&order.id).await {
+                ledger::record_update(ledger::order_entry(&o));
+                order = o;
+            }
+        }
+
+        maker_value += parse(&order.executed_value);
+        maker_fees += parse(&order.fill_fees);
+        if parse(&order.filled_size) > 0.0 {
+            orders.push(order);
+        }
+    }
+
+    let mut taker_value = 0.0;
+    let mut taker_fees = 0.0;
+    let remaining = amount - maker_value - maker_fees;
+    if remaining >= MIN_REMAINING_FUNDS && options.fallback == Fallback::Market {
+        let funds = (remaining * 100.0).floor() / 100.0;
+        logging::info(
+            "Falling back to a market order",
+            fields().product(product_id).amount(funds),
+        );
+        if let Some(order) = place_order(&funds, &product.base_currency).await {
+            ledger::record(ledger::order_entry(&order));
+            // The order's funds include the fee the exchange will charge
+            taker_fees = funds * taker_rate;
+            taker_value = funds - taker_fees;
+            orders.push(await_settled(order).await);
+        }
+    }
+
+    let path = match (maker_value > 0.0, taker_value > 0.0) {
+        (true, false) => ExecutionPath::Maker,
+        (true, true) => ExecutionPath::MakerThenMarket,
+        (false, true) => ExecutionPath::Market,
+        (false, false) => ExecutionPath::GaveUp,
+    };
+    Some(MakerResult {
+        path,
+        orders,
+        maker_value,
+        maker_fees,
+        taker_value,
+        taker_fees,
+        fee_saved: fee_saved(maker_value, maker_fees, taker_rate),
+    })
+}
+
+#[cfg(test)]
+mod tests;
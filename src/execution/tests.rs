@@ -0,0 +1,21 @@
+use super::*;
+
+#[test]
+fn test_floor_to_increment() {
+    assert_eq!(floor_to_increment(19999.999, "0.01"), "19999.99");
+    assert_eq!(floor_to_increment(0.3, "0.1"), "0.3");
+    assert_eq!(
+        floor_to_increment(0.000123456789, "0.00000001"),
+        "0.00012345"
+    );
+    assert_eq!(floor_to_increment(42.9, "1"), "42");
+    assert_eq!(floor_to_increment(0.0049, "0.01"), "0.00");
+}
+
+#[test]
+fn test_fee_saved() {
+    // $100 filled as a maker at 0.4% instead of the 0.6% taker rate
+    let saved = fee_saved(100.0, 0.4, 0.006);
+    assert!((saved - 0.2).abs() < 1e-9);
+    assert_eq!(fee_saved(0.0, 0.0, 0.006), 0.0);
+}
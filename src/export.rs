@@ -0,0 +1,548 @@
+use crate::api::Candlestick;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// How far back from the end of a file to look for the last complete line
+static TAIL_BYTES: u64 = 64 * 1024;
+// Parquet readers do best with row groups of about this many rows
+static ROW_GROUP_ROWS: usize = 100_000;
+
+pub static HEADER: [&str; 6] = ["time", "low", "high", "open", "close", "volume"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// A single row group per 100,000 candles, written once the download finishes
+    Parquet,
+    /// A `candles` table keyed by product, granularity and time, so one database can
+    /// hold many series. Only ever added to, since replacing the database would drop
+    /// every other series in it
+    Sqlite,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            "sqlite" => Ok(Format::Sqlite),
+            _ => Err(format!(
+                "'{}' is not one of csv, jsonl, parquet or sqlite",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            Format::Parquet => "parquet",
+            Format::Sqlite => "sqlite",
+        }
+    }
+
+    /// Whether candles are written a line at a time, so they can go to stdout and a
+    /// half-written file can be repaired
+    pub fn is_line_based(&self) -> bool {
+        matches!(self, Format::Csv | Format::Jsonl)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeFormat {
+    /// Seconds since the epoch, as the exchange returns them
+    Epoch,
+    /// ex: 2021-01-01T00:00:00Z
+    Rfc3339,
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TimeFormat, String> {
+        match s {
+            "epoch" => Ok(TimeFormat::Epoch),
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            _ => Err(format!("'{}' is not one of epoch or rfc3339", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Time {
+    Epoch(i64),
+    Rfc3339(String),
+}
+
+pub fn rfc3339(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .unwrap()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn format_time(timestamp: i64, format: TimeFormat) -> Time {
+    match format {
+        TimeFormat::Epoch => Time::Epoch(timestamp),
+        TimeFormat::Rfc3339 => Time::Rfc3339(rfc3339(timestamp)),
+    }
+}
+
+/// Read a candle time written in either time format
+pub fn parse_time(value: &str) -> Option<i64> {
+    value.parse::<i64>().ok().or_else(|| {
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|t| t.timestamp())
+    })
+}
+
+/// A candle as written to CSV and JSON lines
+#[derive(Debug, Serialize)]
+pub struct Row {
+    pub time: Time,
+    pub low: f64,
+    pub high: f64,
+    pub open: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl Row {
+    pub fn new(candle: &Candlestick, time_format: TimeFormat) -> Row {
+        let Candlestick(time, low, high, open, close, volume) = *candle;
+        Row {
+            time: format_time(time as i64, time_format),
+            low,
+            high,
+            open,
+            close,
+            volume,
+        }
+    }
+}
+
+/// Where `history` writes candles
+pub enum Destination {
+    Stdout,
+    /// Write to a temporary file that replaces this one once the download finishes
+    Replace(PathBuf),
+    /// Add to this file, which may already have candles
+    Append(PathBuf),
+}
+
+enum Sink {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Jsonl(Box<dyn Write>),
+    Parquet(File, Vec<Candlestick>),
+    Sqlite(Connection),
+}
+
+pub struct CandleWriter {
+    sink: Option<Sink>,
+    time_format: TimeFormat,
+    product_id: String,
+    granularity: i64,
+    /// The temporary file being written and the file it will replace
+    replace: Option<(PathBuf, PathBuf)>,
+}
+
+fn write_error(e: impl std::fmt::Display) -> String {
+    format!("Failed to write candles: {}", e)
+}
+
+fn init_sqlite(conn: &Connection) -> Result<(), String> {
+    // No type on time, since it's an integer or text depending on --time-format
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS candles (
+            product_id TEXT NOT NULL,
+            granularity INTEGER NOT NULL,
+            time NOT NULL,
+            low REAL NOT NULL,
+            high REAL NOT NULL,
+            open REAL NOT NULL,
+            close REAL NOT NULL,
+            volume REAL NOT NULL,
+            PRIMARY KEY (product_id, granularity, time)
+        );",
+    )
+    .map_err(|e| format!("Failed to create the candles table: {}", e))
+}
+
+fn open_sink(path: &Path, format: Format, append: bool) -> Result<Sink, String> {
+    let open_error = |e: io::Error| format!("Failed to open {}: {}", path.display(), e);
+    match format {
+        Format::Csv | Format::Jsonl => {
+            if append {
+                trim_partial_line(path)
+                    .map_err(|e| format!("Failed to repair {}: {}", path.display(), e))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(append)
+                .write(true)
+                .truncate(!append)
+                .open(path)
+                .map_err(open_error)?;
+            let empty = file.metadata().map(|m| m.len() == 0).unwrap_or(true);
+            let out: Box<dyn Write> = Box::new(file);
+            if format == Format::Jsonl {
+                return Ok(Sink::Jsonl(out));
+            }
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(out);
+            if empty {
+                writer.write_record(HEADER).map_err(write_error)?;
+            }
+            Ok(Sink::Csv(Box::new(writer)))
+        }
+        Format::Parquet if append => Err(String::from(
+            "Parquet files can't be added to; use --out to write a new one",
+        )),
+        Format::Parquet => Ok(Sink::Parquet(
+            File::create(path).map_err(open_error)?,
+            Vec::new(),
+        )),
+        Format::Sqlite => {
+            let conn = Connection::open(path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            init_sqlite(&conn)?;
+            Ok(Sink::Sqlite(conn))
+        }
+    }
+}
+
+/// Start writing candles of `product_id` to `destination`
+pub fn open(
+    destination: &Destination,
+    format: Format,
+    time_format: TimeFormat,
+    product_id: &str,
+    granularity: i64,
+) -> Result<CandleWriter, String> {
+    let (sink, replace) = match destination {
+        Destination::Stdout if format.is_line_based() => {
+            let out: Box<dyn Write> = Box::new(io::stdout());
+            let sink = if format == Format::Jsonl {
+                Sink::Jsonl(out)
+            } else {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(out);
+                writer.write_record(HEADER).map_err(write_error)?;
+                Sink::Csv(Box::new(writer))
+            };
+            (sink, None)
+        }
+        Destination::Stdout => {
+            return Err(format!(
+                "{} can't be written to stdout; use --out",
+                format.as_str()
+            ))
+        }
+        Destination::Replace(_) if format == Format::Sqlite => {
+            return Err(String::from(
+                "--out would replace the whole database; use --append to add to it",
+            ))
+        }
+        Destination::Replace(path) => {
+            let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+            // Left over from a download that was killed
+            let _ = fs::remove_file(&tmp);
+            (open_sink(&tmp, format, false)?, Some((tmp, path.clone())))
+        }
+        Destination::Append(path) => (open_sink(path, format, true)?, None),
+    };
+    let mut writer = CandleWriter {
+        sink: Some(sink),
+        time_format,
+        product_id: String::from(product_id),
+        granularity,
+        replace,
+    };
+    writer.flush()?;
+    Ok(writer)
+}
+
+impl CandleWriter {
+    /// Write candles, in order. Line formats are flushed and each call to sqlite is a
+    /// transaction, so what's been written survives an interruption
+    pub fn write(&mut self, candles: &[Candlestick]) -> Result<(), String> {
+        let time_format = self.time_format;
+        match self.sink.as_mut().expect("Candles written after finishing") {
+            Sink::Csv(writer) => {
+                for c in candles {
+                    writer
+                        .serialize(Row::new(c, time_format))
+                        .map_err(write_error)?;
+                }
+            }
+            Sink::Jsonl(out) => {
+                for c in candles {
+                    let line = serde_json::to_string(&Row::new(c, time_format))
+                        .expect("Candles are always serializable");
+                    writeln!(out, "{}", line).map_err(write_error)?;
+                }
+            }
+            Sink::Parquet(_, rows) => rows.extend_from_slice(candles),
+            Sink::Sqlite(conn) => {
+                let tx = conn.transaction().map_err(write_error)?;
+                {
+                    let mut stmt = tx
+                        .prepare(
+                            "INSERT OR REPLACE INTO candles
+                                (product_id, granularity, time, low, high, open, close, volume)
+                             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        )
+                        .map_err(write_error)?;
+                    for c in candles {
+                        let time = match format_time(c.0 as i64, time_format) {
+                            Time::Epoch(t) => SqlValue::Integer(t),
+                            Time::Rfc3339(t) => SqlValue::Text(t),
+                        };
+                        stmt.execute(params![
+                            self.product_id,
+                            self.granularity,
+                            time,
+                            c.1,
+                            c.2,
+                            c.3,
+                            c.4,
+                            c.5
+                        ])
+                        .map_err(write_error)?;
+                    }
+                }
+                tx.commit().map_err(write_error)?;
+            }
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        match self.sink.as_mut() {
+            Some(Sink::Csv(writer)) => writer.flush().map_err(write_error),
+            Some(Sink::Jsonl(out)) => out.flush().map_err(write_error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Finish writing, and move the file into place if it's replacing one
+    pub fn finish(mut self) -> Result<(), String> {
+        self.flush()?;
+        if let Some(Sink::Parquet(file, rows)) = self.sink.take() {
+            write_parquet(file, &rows, self.time_format)?;
+        }
+        // Close the database or file before moving it
+        self.sink = None;
+        if let Some((tmp, path)) = self.replace.take() {
+            fs::rename(&tmp, &path)
+                .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CandleWriter {
+    /// An unfinished download never replaces the file it was meant for
+    fn drop(&mut self) {
+        self.sink = None;
+        if let Some((tmp, _)) = &self.replace {
+            let _ = fs::remove_file(tmp);
+        }
+    }
+}
+
+fn write_parquet(file: File, rows: &[Candlestick], time_format: TimeFormat) -> Result<(), String> {
+    let time_type = match time_format {
+        TimeFormat::Epoch => "INT64 time",
+        TimeFormat::Rfc3339 => "BYTE_ARRAY time (UTF8)",
+    };
+    let schema = parse_message_type(&format!(
+        "message candles {{
+            REQUIRED {};
+            REQUIRED DOUBLE low;
+            REQUIRED DOUBLE high;
+            REQUIRED DOUBLE open;
+            REQUIRED DOUBLE close;
+            REQUIRED DOUBLE volume;
+        }}",
+        time_type
+    ))
+    .map_err(write_error)?;
+    let properties = WriterProperties::builder().build();
+    let mut writer = SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties))
+        .map_err(write_error)?;
+    for chunk in rows.chunks(ROW_GROUP_ROWS) {
+        let mut group = writer.next_row_group().map_err(write_error)?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column().map_err(write_error)? {
+            let written = match (index, time_format) {
+                (0, TimeFormat::Epoch) => {
+                    let times: Vec<i64> = chunk.iter().map(|c| c.0 as i64).collect();
+                    column.typed::<Int64Type>().write_batch(&times, None, None)
+                }
+                (0, TimeFormat::Rfc3339) => {
+                    let times: Vec<ByteArray> = chunk
+                        .iter()
+                        .map(|c| ByteArray::from(rfc3339(c.0 as i64).as_str()))
+                        .collect();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&times, None, None)
+                }
+                _ => {
+                    let values: Vec<f64> = chunk
+                        .iter()
+                        .map(|c| match index {
+                            1 => c.1,
+                            2 => c.2,
+                            3 => c.3,
+                            4 => c.4,
+                            _ => c.5,
+                        })
+                        .collect();
+                    column
+                        .typed::<DoubleType>()
+                        .write_batch(&values, None, None)
+                }
+            };
+            written.map_err(write_error)?;
+            column.close().map_err(write_error)?;
+            index += 1;
+        }
+        group.close().map_err(write_error)?;
+    }
+    writer.close().map_err(write_error)?;
+    Ok(())
+}
+
+/// Cut off a line left half-written by an interrupted run, so appending starts on a new line
+pub fn trim_partial_line(path: &Path) -> io::Result<()> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == IoErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let from = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(from))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    if tail.last().is_none_or(|b| *b == b'\n') {
+        return Ok(());
+    }
+    let keep = match tail.iter().rposition(|b| *b == b'\n') {
+        Some(i) => from + i as u64 + 1,
+        None if from == 0 => 0,
+        None => {
+            return Err(io::Error::new(
+                IoErrorKind::InvalidData,
+                "the last line is too long to be a candle",
+            ))
+        }
+    };
+    file.set_len(keep)
+}
+
+fn not_history(path: &Path) -> String {
+    format!(
+        "{} has a candle without a time; is it from `hodl history` in this format?",
+        path.display()
+    )
+}
+
+/// The time of the latest candle of `product_id` in a file written by `history`, if
+/// there are any
+pub fn last_time(
+    path: &Path,
+    format: Format,
+    product_id: &str,
+    granularity: i64,
+) -> Result<Option<i64>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let read_error =
+        |e: &dyn std::fmt::Display| format!("Failed to read {}: {}", path.display(), e);
+    let mut last = None;
+    match format {
+        Format::Csv => {
+            let file = File::open(path).map_err(|e| read_error(&e))?;
+            for record in csv::Reader::from_reader(file).records() {
+                let record = record.map_err(|e| read_error(&e))?;
+                let time = record
+                    .get(0)
+                    .and_then(parse_time)
+                    .ok_or_else(|| not_history(path))?;
+                last = last.max(Some(time));
+            }
+        }
+        Format::Jsonl => {
+            let file = File::open(path).map_err(|e| read_error(&e))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| read_error(&e))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let row: Value = serde_json::from_str(&line).map_err(|e| read_error(&e))?;
+                let time = match &row["time"] {
+                    Value::Number(n) => n.as_i64(),
+                    Value::String(s) => parse_time(s),
+                    _ => None,
+                }
+                .ok_or_else(|| not_history(path))?;
+                last = last.max(Some(time));
+            }
+        }
+        Format::Parquet => {
+            return Err(String::from(
+                "Parquet files can't be added to; use --out to write a new one",
+            ))
+        }
+        Format::Sqlite => {
+            let conn = Connection::open(path).map_err(|e| read_error(&e))?;
+            init_sqlite(&conn)?;
+            // Text sorts after integers, but one series is written in one time format
+            let time: Option<SqlValue> = conn
+                .query_row(
+                    "SELECT time FROM candles WHERE product_id = ?1 AND granularity = ?2
+                     ORDER BY time DESC LIMIT 1",
+                    params![product_id, granularity],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| read_error(&e))?;
+            last = match time {
+                None => None,
+                Some(SqlValue::Integer(t)) => Some(t),
+                Some(SqlValue::Text(t)) => Some(parse_time(&t).ok_or_else(|| not_history(path))?),
+                Some(_) => return Err(not_history(path)),
+            };
+        }
+    }
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,242 @@
+use super::*;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+use tempfile::tempdir;
+
+fn candles() -> Vec<Candlestick> {
+    vec![
+        Candlestick(60, 1.0, 2.0, 1.5, 1.75, 10.0),
+        Candlestick(120, 1.5, 3.0, 1.75, 2.5, 20.0),
+    ]
+}
+
+#[test]
+fn test_last_time_and_partial_lines() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.csv");
+    assert_eq!(last_time(&path, Format::Csv, "BTC-USD", 60).unwrap(), None);
+
+    // Files from older versions have each window newest first, so the last row isn't the latest
+    fs::write(
+        &path,
+        "time,low,high,open,close,volume\n\
+         120,1,2,1,2,10\n\
+         60,1,2,1,2,10\n\
+         300,1,2,1,2,10\n\
+         240,1,2,1,2,10\n\
+         180,1,",
+    )
+    .unwrap();
+    trim_partial_line(&path).unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .ends_with("240,1,2,1,2,10\n"));
+    assert_eq!(
+        last_time(&path, Format::Csv, "BTC-USD", 60).unwrap(),
+        Some(300)
+    );
+
+    // Nothing to trim
+    trim_partial_line(&path).unwrap();
+    assert_eq!(
+        last_time(&path, Format::Csv, "BTC-USD", 60).unwrap(),
+        Some(300)
+    );
+
+    fs::write(&path, "not,a,history,file\nabc,1,2,3\n").unwrap();
+    assert!(last_time(&path, Format::Csv, "BTC-USD", 60).is_err());
+
+    let path = dir.path().join("candles.jsonl");
+    fs::write(
+        &path,
+        "{\"time\":\"1970-01-01T00:02:00Z\",\"low\":1.0}\n{\"time\":60,\"low\":1.0}\n",
+    )
+    .unwrap();
+    assert_eq!(
+        last_time(&path, Format::Jsonl, "BTC-USD", 60).unwrap(),
+        Some(120)
+    );
+}
+
+#[test]
+fn test_write_line_formats() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.csv");
+    let destination = Destination::Append(path.clone());
+    let mut writer = open(
+        &destination,
+        Format::Csv,
+        TimeFormat::Rfc3339,
+        "BTC-USD",
+        60,
+    )
+    .unwrap();
+    writer.write(&candles()).unwrap();
+    writer.finish().unwrap();
+    // Appending again doesn't repeat the header
+    let writer = open(
+        &destination,
+        Format::Csv,
+        TimeFormat::Rfc3339,
+        "BTC-USD",
+        60,
+    )
+    .unwrap();
+    writer.finish().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "time,low,high,open,close,volume\n\
+         1970-01-01T00:01:00Z,1.0,2.0,1.5,1.75,10.0\n\
+         1970-01-01T00:02:00Z,1.5,3.0,1.75,2.5,20.0\n"
+    );
+
+    let path = dir.path().join("candles.jsonl");
+    let destination = Destination::Append(path.clone());
+    let mut writer = open(
+        &destination,
+        Format::Jsonl,
+        TimeFormat::Epoch,
+        "BTC-USD",
+        60,
+    )
+    .unwrap();
+    writer.write(&candles()[..1]).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "{\"time\":60,\"low\":1.0,\"high\":2.0,\"open\":1.5,\"close\":1.75,\"volume\":10.0}\n"
+    );
+    assert_eq!(
+        last_time(&path, Format::Jsonl, "BTC-USD", 60).unwrap(),
+        Some(60)
+    );
+}
+
+#[test]
+fn test_replace_is_atomic() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.csv");
+    fs::write(&path, "old").unwrap();
+    let destination = Destination::Replace(path.clone());
+
+    // An unfinished download leaves the old file alone
+    let mut writer = open(&destination, Format::Csv, TimeFormat::Epoch, "BTC-USD", 60).unwrap();
+    writer.write(&candles()).unwrap();
+    drop(writer);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    assert!(!dir.path().join("candles.csv.tmp").exists());
+
+    let mut writer = open(&destination, Format::Csv, TimeFormat::Epoch, "BTC-USD", 60).unwrap();
+    writer.write(&candles()).unwrap();
+    writer.finish().unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .ends_with("120,1.5,3.0,1.75,2.5,20.0\n"));
+
+    assert!(open(
+        &Destination::Stdout,
+        Format::Parquet,
+        TimeFormat::Epoch,
+        "BTC-USD",
+        60
+    )
+    .is_err());
+}
+
+#[test]
+fn test_write_sqlite() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.sqlite3");
+    let destination = Destination::Append(path.clone());
+    let mut writer = open(
+        &destination,
+        Format::Sqlite,
+        TimeFormat::Epoch,
+        "BTC-USD",
+        60,
+    )
+    .unwrap();
+    writer.write(&candles()).unwrap();
+    // Writing a candle again replaces it
+    writer.write(&candles()[1..]).unwrap();
+    writer.finish().unwrap();
+    let mut writer = open(
+        &destination,
+        Format::Sqlite,
+        TimeFormat::Epoch,
+        "ETH-USD",
+        60,
+    )
+    .unwrap();
+    writer.write(&candles()[..1]).unwrap();
+    writer.finish().unwrap();
+
+    let conn = Connection::open(&path).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM candles", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(
+        last_time(&path, Format::Sqlite, "BTC-USD", 60).unwrap(),
+        Some(120)
+    );
+    assert_eq!(
+        last_time(&path, Format::Sqlite, "ETH-USD", 60).unwrap(),
+        Some(60)
+    );
+    assert_eq!(
+        last_time(&path, Format::Sqlite, "BTC-USD", 300).unwrap(),
+        None
+    );
+
+    // Replacing the database would lose the other series
+    assert!(open(
+        &Destination::Replace(path.clone()),
+        Format::Sqlite,
+        TimeFormat::Epoch,
+        "BTC-USD",
+        60
+    )
+    .is_err());
+    assert!(!dir.path().join("candles.sqlite3.tmp").exists());
+    assert_eq!(
+        last_time(&path, Format::Sqlite, "ETH-USD", 60).unwrap(),
+        Some(60)
+    );
+}
+
+#[test]
+fn test_write_parquet() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.parquet");
+    let destination = Destination::Replace(path.clone());
+    let mut writer = open(
+        &destination,
+        Format::Parquet,
+        TimeFormat::Rfc3339,
+        "BTC-USD",
+        60,
+    )
+    .unwrap();
+    writer.write(&candles()).unwrap();
+    writer.finish().unwrap();
+
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let rows: Vec<_> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].get_string(0).unwrap(), "1970-01-01T00:02:00Z");
+    assert_eq!(rows[1].get_double(5).unwrap(), 20.0);
+
+    assert!(open(
+        &Destination::Append(path),
+        Format::Parquet,
+        TimeFormat::Epoch,
+        "BTC-USD",
+        60
+    )
+    .is_err());
+}
@@ -0,0 +1,70 @@
+use crate::api::{get_fees, Fees, Fill};
+use crate::logging::{self, fields};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FeeSummary {
+    pub fills: usize,
+    pub value: f64,
+    pub fees: f64,
+    pub maker_fees: f64,
+    pub taker_fees: f64,
+}
+
+fn parse(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
+/// Total up the value traded and the fees paid across a set of fills
+pub fn summarize_fills(fills: &[Fill]) -> FeeSummary {
+    let mut summary = FeeSummary::default();
+    for fill in fills {
+        let fee = parse(&fill.fee);
+        summary.fills += 1;
+        summary.value += parse(&fill.price) * parse(&fill.size);
+        summary.fees += fee;
+        // Liquidity is "M" for maker fills and "T" for taker fills
+        if fill.liquidity == "M" {
+            summary.maker_fees += fee;
+        } else {
+            summary.taker_fees += fee;
+        }
+    }
+    summary
+}
+
+/// Split `amount` USD into the estimated fee and what's left to buy with
+pub fn estimate_fee(amount: f64, rate: f64) -> (f64, f64) {
+    // The fee comes out of the funds: amount = net + net * rate
+    let net = amount / (1.0 + rate);
+    (amount - net, net)
+}
+
+pub fn format_rate(rate: &str) -> String {
+    format!("{:.2}%", parse(rate) * 100.0)
+}
+
+/// Log the estimated fee of a buy at the current maker or taker rate.
+/// Returns the fee rates so callers don't need to fetch them again.
+pub async fn print_fee_estimate(amount: f64, maker: bool) -> Option<Fees> {
+    let fees = get_fees().await?;
+    let (name, rate) = if maker {
+        ("maker", &fees.maker_fee_rate)
+    } else {
+        ("taker", &fees.taker_fee_rate)
+    };
+    let (fee, net) = estimate_fee(amount, parse(rate));
+    logging::info(
+        &format!(
+            "Estimated fee: ${:.2} ({} {}); net amount ${:.2}",
+            fee,
+            format_rate(rate),
+            name,
+            net
+        ),
+        fields().amount(amount),
+    );
+    Some(fees)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,40 @@
+use super::*;
+use serde_json::json;
+
+fn fill(price: &str, size: &str, fee: &str, liquidity: &str) -> Fill {
+    serde_json::from_value(json!({
+        "trade_id": 74,
+        "product_id": "BTC-USD",
+        "order_id": "d50ec984-77a8-460a-b958-66f114b0de9b",
+        "created_at": "2014-11-07T22:19:28.578544Z",
+        "liquidity": liquidity,
+        "price": price,
+        "size": size,
+        "fee": fee,
+        "side": "buy",
+        "settled": true,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_summarize_fills() {
+    let summary = summarize_fills(&[
+        fill("10000.00", "0.01", "0.50", "T"),
+        fill("20000.00", "0.005", "0.25", "M"),
+    ]);
+    assert_eq!(summary.fills, 2);
+    assert!((summary.value - 200.0).abs() < 1e-9);
+    assert!((summary.fees - 0.75).abs() < 1e-9);
+    assert!((summary.maker_fees - 0.25).abs() < 1e-9);
+    assert!((summary.taker_fees - 0.5).abs() < 1e-9);
+    assert_eq!(summarize_fills(&[]), FeeSummary::default());
+}
+
+#[test]
+fn test_estimate_fee() {
+    let (fee, net) = estimate_fee(100.5, 0.005);
+    assert!((fee - 0.5).abs() < 1e-9);
+    assert!((net - 100.0).abs() < 1e-9);
+    assert_eq!(format_rate("0.0060"), "0.60%");
+}
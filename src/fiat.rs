@@ -0,0 +1,66 @@
+use crate::api::{get_payment_methods, PaymentMethod};
+use std::env;
+
+/// Refuse amounts that aren't positive or exceed the configured maximum
+pub fn check_amount(amount: f64, max: Option<f64>, action: &str) -> Result<(), String> {
+    if amount <= 0.0 {
+        return Err(format!(
+            "Cannot {} ${}; the amount must be positive",
+            action, amount
+        ));
+    }
+    match max {
+        Some(m) if amount > m => Err(format!(
+            "Refusing to {} ${}; the configured maximum is ${}",
+            action, amount, m
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Find a payment method by its id, or by its name ignoring case
+pub fn find_payment_method<'a>(
+    methods: &'a [PaymentMethod],
+    selector: &str,
+) -> Option<&'a PaymentMethod> {
+    methods.iter().find(|m| m.id == selector).or_else(|| {
+        methods
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(selector))
+    })
+}
+
+/// The id of the bank account to move money with: the payment method named by
+/// `selector` when given, otherwise the BANK_ID environment variable
+pub async fn payment_method_id(
+    selector: Option<&str>,
+    withdrawing: bool,
+) -> Result<String, String> {
+    let selector = match selector {
+        Some(s) => s,
+        None => {
+            return env::var("BANK_ID").map_err(|_| {
+                String::from(
+                    "Set the BANK_ID environment variable or pass --payment-method.\n\
+                     Looking for your bank id? Use the 'payment-methods' command",
+                )
+            })
+        }
+    };
+
+    let methods = get_payment_methods()
+        .await
+        .ok_or_else(|| String::from("Failed to fetch payment methods"))?;
+    let method = find_payment_method(&methods, selector)
+        .ok_or_else(|| format!("No payment method with the id or name '{}'", selector))?;
+    if withdrawing && !method.allow_withdraw {
+        return Err(format!("'{}' does not allow withdrawals", method.name));
+    }
+    if !withdrawing && !method.allow_deposit {
+        return Err(format!("'{}' does not allow deposits", method.name));
+    }
+    Ok(method.id.clone())
+}
+
+#[cfg(test)]
+mod tests;
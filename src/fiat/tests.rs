@@ -0,0 +1,47 @@
+use super::*;
+use serde_json::json;
+
+fn method(id: &str, name: &str) -> PaymentMethod {
+    serde_json::from_value(json!({
+        "id": id,
+        "type": "ach_bank_account",
+        "name": name,
+        "currency": "USD",
+        "primary_buy": true,
+        "primary_sell": true,
+        "allow_buy": true,
+        "allow_sell": true,
+        "allow_deposit": true,
+        "allow_withdraw": true,
+        "limits": {}
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_check_amount() {
+    assert!(check_amount(50.0, None, "deposit").is_ok());
+    assert!(check_amount(50.0, Some(50.0), "deposit").is_ok());
+    assert!(check_amount(50.01, Some(50.0), "deposit").is_err());
+    assert!(check_amount(0.0, None, "withdraw").is_err());
+    assert!(check_amount(-5.0, None, "withdraw").is_err());
+}
+
+#[test]
+fn test_find_payment_method() {
+    let methods = vec![
+        method(
+            "bc6d7162-d984-5ffa-963c-a493b1c1370b",
+            "Bank of America - eBan... ********7134",
+        ),
+        method(
+            "e49c8d15-547b-464e-ac3d-4b9d20b360ec",
+            "Credit Union ********0000",
+        ),
+    ];
+    let by_id = find_payment_method(&methods, "e49c8d15-547b-464e-ac3d-4b9d20b360ec");
+    assert_eq!(by_id.unwrap().name, "Credit Union ********0000");
+    let by_name = find_payment_method(&methods, "credit union ********0000");
+    assert_eq!(by_name.unwrap().id, "e49c8d15-547b-464e-ac3d-4b9d20b360ec");
+    assert!(find_payment_method(&methods, "Chase").is_none());
+}
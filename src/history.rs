@@ -0,0 +1,346 @@
+use crate::api::{calc_num_requests, get_history_window, Candlestick, CANDLES_PER_REQUEST};
+use crate::export::{self, rfc3339, CandleWriter, Destination, Format, TimeFormat};
+use crate::logging::{self, fields};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// The public API allows a few requests a second; one keeps well clear of it
+static REQUEST_INTERVAL_MILLIS: u64 = 1000;
+// A window that fails this many times in a row ends the download
+static ATTEMPTS: u32 = 4;
+static RETRY_MILLIS: u64 = 2000;
+
+pub struct HistoryOptions {
+    pub product_id: String,
+    /// ISO 8601
+    pub start: String,
+    pub end: String,
+    /// Seconds per candle
+    pub granularity: String,
+    /// Appending to a file resumes after the candles already in it
+    pub destination: Destination,
+    pub format: Format,
+    pub time_format: TimeFormat,
+    /// Log the stretches of time the exchange has no candles for
+    pub report_gaps: bool,
+}
+
+/// How far a download appending to a file got, saved after each window so an
+/// interrupted download can pick up where it stopped
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub product_id: String,
+    pub granularity: i64,
+    /// The start of the next window to fetch, in seconds since the epoch
+    pub next_start: i64,
+}
+
+pub fn checkpoint_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.checkpoint", path.display()))
+}
+
+pub fn read_checkpoint(path: &Path) -> Result<Option<Checkpoint>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == IoErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| format!("Invalid checkpoint in {}: {}", path.display(), e))
+}
+
+fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<(), String> {
+    // Write then rename, so an interruption never leaves half a checkpoint
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    let json = serde_json::to_string(checkpoint).expect("Checkpoints are always serializable");
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Where a download should start: at `start`, or after what's already been downloaded,
+/// whichever is later
+pub fn resume_point(
+    start: i64,
+    last: Option<i64>,
+    checkpoint: Option<&Checkpoint>,
+    granularity: i64,
+) -> i64 {
+    let after_last = last.map(|t| t + granularity).unwrap_or(start);
+    let after_checkpoint = checkpoint.map(|c| c.next_start).unwrap_or(start);
+    start.max(after_last).max(after_checkpoint)
+}
+
+fn parse_time(time: &str, name: &str) -> Result<i64, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|t| t.timestamp())
+        .map_err(|e| format!("Failed to parse {} date '{}': {}", name, time, e))
+}
+
+/// Open `path` for appending and work out where to resume, checking that it's being
+/// continued with the same product and granularity. Returns the writer, where to
+/// resume and the time of the last candle already written
+fn prepare_append(
+    path: &Path,
+    options: &HistoryOptions,
+    start: i64,
+    granularity: i64,
+) -> Result<(CandleWriter, i64, Option<i64>), String> {
+    let checkpoint_path = checkpoint_path(path);
+    let mut checkpoint = read_checkpoint(&checkpoint_path)?;
+    if !path.exists() {
+        // Whatever the checkpoint was for is gone
+        checkpoint = None;
+    }
+    if let Some(c) = &checkpoint {
+        if c.product_id != options.product_id || c.granularity != granularity {
+            return Err(format!(
+                "{} is an unfinished download of {} at {}s candles; finish it or use another file",
+                path.display(),
+                c.product_id,
+                c.granularity
+            ));
+        }
+    }
+    // Opening repairs a line cut off by an interruption, so do it before reading
+    let writer = export::open(
+        &Destination::Append(path.to_path_buf()),
+        options.format,
+        options.time_format,
+        &options.product_id,
+        granularity,
+    )?;
+    let last = export::last_time(path, options.format, &options.product_id, granularity)?;
+    let from = resume_point(start, last, checkpoint.as_ref(), granularity);
+    if from > start {
+        logging::info(
+            &format!("Resuming {} from {}", path.display(), rfc3339(from)),
+            fields().product(&options.product_id),
+        );
+    }
+    Ok((writer, from, last))
+}
+
+/// Sort a window's candles oldest first, keeping only those within the window that
+/// come after `after`, the last candle written. Adjacent windows share a boundary, so
+/// this is what keeps its candle from being written twice
+pub fn order_window(
+    mut candles: Vec<Candlestick>,
+    after: Option<i64>,
+    start: i64,
+    end: i64,
+) -> Vec<Candlestick> {
+    candles.retain(|c| {
+        let time = c.0 as i64;
+        time >= start && time <= end && after.is_none_or(|a| time > a)
+    });
+    candles.sort_by_key(|c| c.0);
+    candles.dedup_by_key(|c| c.0);
+    candles
+}
+
+/// Candle times from `from` to `to` (inclusive) that the exchange returned nothing for
+#[derive(Debug, PartialEq)]
+pub struct Gap {
+    pub from: i64,
+    pub to: i64,
+    pub missing: i64,
+}
+
+/// The gap between consecutive candles at `previous` and `next`, if there is one
+pub fn find_gap(previous: i64, next: i64, granularity: i64) -> Option<Gap> {
+    if next - previous <= granularity {
+        return None;
+    }
+    Some(Gap {
+        from: previous + granularity,
+        to: next - granularity,
+        missing: (next - previous) / granularity - 1,
+    })
+}
+
+fn log_gap(gap: &Gap, product_id: &str) {
+    logging::info(
+        &format!(
+            "No candles from {} to {} ({} missing)",
+            rfc3339(gap.from),
+            rfc3339(gap.to),
+            gap.missing
+        ),
+        fields().product(product_id),
+    );
+}
+
+/// Fetch a window, trying again a few times if it fails in a way that might pass
+async fn fetch_window(
+    client: &Client,
+    options: &HistoryOptions,
+    start: i64,
+    end: i64,
+) -> Result<Vec<Candlestick>, String> {
+    let mut attempt = 1;
+    loop {
+        let result = get_history_window(
+            client,
+            &options.product_id,
+            &rfc3339(start),
+            &rfc3339(end),
+            &options.granularity,
+        )
+        .await;
+        match result {
+            Ok(c) => return Ok(c),
+            // Anything else, like an unknown product, would only fail the same way again
+            Err(e) if e.retryable && attempt < ATTEMPTS => {
+                logging::warn(
+                    &format!(
+                        "{}; trying again (attempt {} of {})",
+                        e.message,
+                        attempt + 1,
+                        ATTEMPTS
+                    ),
+                    fields().product(&options.product_id),
+                );
+                sleep(Duration::from_millis(RETRY_MILLIS * 2u64.pow(attempt - 1))).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.message),
+        }
+    }
+}
+
+/// Download the candles from `options.start` to `options.end` in windows of 300.
+/// When appending to a file, each window is written and checkpointed before the next
+/// is fetched, so stopping at any point loses nothing
+pub async fn download(options: &HistoryOptions) -> Result<(), String> {
+    let granularity = options
+        .granularity
+        .parse::<i64>()
+        .ok()
+        .filter(|g| *g > 0)
+        .ok_or("Granularity must be a number (in seconds)")?;
+    let start = parse_time(&options.start, "start")?;
+    let end = parse_time(&options.end, "end")?;
+
+    let (mut writer, from, mut last) = match &options.destination {
+        Destination::Append(path) => prepare_append(path, options, start, granularity)?,
+        destination => (
+            export::open(
+                destination,
+                options.format,
+                options.time_format,
+                &options.product_id,
+                granularity,
+            )?,
+            start,
+            None,
+        ),
+    };
+    let append = match &options.destination {
+        Destination::Append(path) => Some(path),
+        _ => None,
+    };
+
+    let client = Client::builder()
+        .user_agent("hodl")
+        .build()
+        .map_err(|e| format!("Failed to fetch candles: {}", e))?;
+    let span = granularity * CANDLES_PER_REQUEST;
+    // Candle times are multiples of the granularity, so the first one expected is the
+    // first multiple at or after the start
+    let mut previous = last
+        .unwrap_or((from + granularity - 1).div_euclid(granularity) * granularity - granularity);
+    let mut gaps = Vec::new();
+    let num_requests = if from < end {
+        calc_num_requests(&rfc3339(from), &rfc3339(end), granularity)
+    } else {
+        0
+    };
+    for i in 0..num_requests {
+        let window_start = from + i * span;
+        if window_start >= end {
+            break;
+        }
+        let window_end = (window_start + span).min(end);
+        logging::debug(
+            &format!(
+                "Fetching candles from {} (window {} of {})",
+                rfc3339(window_start),
+                i + 1,
+                num_requests
+            ),
+            fields().product(&options.product_id),
+        );
+        let candles = match fetch_window(&client, options, window_start, window_end).await {
+            Ok(c) => c,
+            Err(e) if append.is_some() => {
+                return Err(format!("{}; run the same command again to resume", e))
+            }
+            Err(e) => return Err(e),
+        };
+        let candles = order_window(candles, last, window_start, window_end);
+        for c in &candles {
+            let time = c.0 as i64;
+            if options.report_gaps {
+                if let Some(gap) = find_gap(previous, time, granularity) {
+                    log_gap(&gap, &options.product_id);
+                    gaps.push(gap);
+                }
+            }
+            previous = time;
+            last = Some(time);
+        }
+        writer.write(&candles)?;
+        if let Some(path) = append {
+            write_checkpoint(
+                &checkpoint_path(path),
+                &Checkpoint {
+                    product_id: options.product_id.clone(),
+                    granularity,
+                    next_start: window_end,
+                },
+            )?;
+        }
+
+        sleep(Duration::from_millis(REQUEST_INTERVAL_MILLIS)).await;
+    }
+
+    if options.report_gaps {
+        // The candle for the current interval may not exist yet, so don't expect any past now
+        let now = Utc::now().timestamp() - granularity;
+        let last_expected = end.min(now).div_euclid(granularity) * granularity;
+        if let Some(gap) = find_gap(previous, last_expected + granularity, granularity) {
+            log_gap(&gap, &options.product_id);
+            gaps.push(gap);
+        }
+        logging::info(
+            &format!(
+                "Found {} gaps ({} missing candles)",
+                gaps.len(),
+                gaps.iter().map(|g| g.missing).sum::<i64>()
+            ),
+            fields().product(&options.product_id),
+        );
+    }
+
+    writer.finish()?;
+    // A finished download needs no checkpoint; the next one resumes from the last candle
+    if let Some(path) = append {
+        let checkpoint = checkpoint_path(path);
+        if let Err(e) = fs::remove_file(&checkpoint) {
+            if e.kind() != IoErrorKind::NotFound {
+                return Err(format!("Failed to remove {}: {}", checkpoint.display(), e));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,96 @@
+use super::*;
+use tempfile::tempdir;
+
+#[test]
+fn test_resume_point() {
+    let checkpoint = Checkpoint {
+        product_id: String::from("BTC-USD"),
+        granularity: 60,
+        next_start: 9000,
+    };
+    assert_eq!(resume_point(1000, None, None, 60), 1000);
+    assert_eq!(resume_point(1000, Some(5000), None, 60), 5060);
+    // A checkpoint past the last candle means the windows after it were empty
+    assert_eq!(resume_point(1000, Some(5000), Some(&checkpoint), 60), 9000);
+    // Asking for a later start than what's there skips ahead
+    assert_eq!(
+        resume_point(20000, Some(5000), Some(&checkpoint), 60),
+        20000
+    );
+}
+
+#[test]
+fn test_prepare_append_checks_checkpoint() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("candles.csv");
+    let options = HistoryOptions {
+        product_id: String::from("BTC-USD"),
+        start: String::from("1970-01-01T00:00:00Z"),
+        end: String::from("1970-01-02T00:00:00Z"),
+        granularity: String::from("60"),
+        report_gaps: false,
+        destination: Destination::Append(path.clone()),
+        format: Format::Csv,
+        time_format: TimeFormat::Epoch,
+    };
+    let checkpoint = Checkpoint {
+        product_id: String::from("ETH-USD"),
+        granularity: 60,
+        next_start: 600,
+    };
+    write_checkpoint(&checkpoint_path(&path), &checkpoint).unwrap();
+    assert_eq!(
+        read_checkpoint(&checkpoint_path(&path)).unwrap(),
+        Some(checkpoint)
+    );
+
+    // Without the file, the checkpoint is ignored and the download starts over
+    let (_, from, last) = prepare_append(&path, &options, 0, 60).unwrap();
+    assert_eq!((from, last), (0, None));
+
+    fs::write(&path, "time,low,high,open,close,volume\n120,1,2,1,2,10\n").unwrap();
+    assert!(prepare_append(&path, &options, 0, 60)
+        .map(|_| ())
+        .unwrap_err()
+        .contains("unfinished download of ETH-USD"));
+
+    fs::remove_file(checkpoint_path(&path)).unwrap();
+    let (_, from, last) = prepare_append(&path, &options, 0, 60).unwrap();
+    assert_eq!((from, last), (180, Some(120)));
+}
+
+fn candle(time: u64) -> Candlestick {
+    Candlestick(time, 1.0, 2.0, 1.0, 2.0, 10.0)
+}
+
+#[test]
+fn test_order_window() {
+    // Newest first, with the boundary candle already written by the previous window
+    let window = vec![candle(300), candle(240), candle(120), candle(60)];
+    let times: Vec<u64> = order_window(window, Some(60), 60, 300)
+        .iter()
+        .map(|c| c.0)
+        .collect();
+    assert_eq!(times, vec![120, 240, 300]);
+
+    // Anything outside the window, or repeated, is dropped
+    let window = vec![candle(420), candle(360), candle(360), candle(0)];
+    let times: Vec<u64> = order_window(window, None, 60, 400)
+        .iter()
+        .map(|c| c.0)
+        .collect();
+    assert_eq!(times, vec![360]);
+}
+
+#[test]
+fn test_find_gap() {
+    assert_eq!(find_gap(60, 120, 60), None);
+    assert_eq!(
+        find_gap(60, 300, 60),
+        Some(Gap {
+            from: 120,
+            to: 240,
+            missing: 3
+        })
+    );
+}
@@ -0,0 +1,413 @@
+use crate::api::{Conversion, DepositResponse, Order, RawBody, WithdrawalResponse};
+use crate::config::hodl_home;
+use crate::logging::{self, fields, ErrorKind};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+
+/// The kinds of money-moving request recorded in the ledger
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Order,
+    Deposit,
+    /// A crypto withdrawal to an external address
+    Withdrawal,
+    FiatWithdrawal,
+    Conversion,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Order => "order",
+            Kind::Deposit => "deposit",
+            Kind::Withdrawal => "withdrawal",
+            Kind::FiatWithdrawal => "fiat-withdrawal",
+            Kind::Conversion => "conversion",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "order" => Ok(Kind::Order),
+            "deposit" => Ok(Kind::Deposit),
+            "withdrawal" => Ok(Kind::Withdrawal),
+            "fiat-withdrawal" => Ok(Kind::FiatWithdrawal),
+            "conversion" => Ok(Kind::Conversion),
+            _ => Err(format!("'{}' is not a ledger entry type", s)),
+        }
+    }
+}
+
+/// One request hodl made that moved money, as recorded in the ledger
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// When hodl first recorded the entry, in UTC
+    pub recorded_at: String,
+    pub updated_at: String,
+    pub kind: Kind,
+    /// The exchange's id for the order, transfer or conversion
+    pub exchange_id: String,
+    /// The product id of an order (ex: BTC-USD), otherwise the currency moved
+    pub product: String,
+    pub side: Option<String>,
+    /// USD spent or received for orders, otherwise the amount of `product` moved
+    pub amount: String,
+    pub size: Option<String>,
+    pub fee: Option<String>,
+    pub status: String,
+    /// The exchange's response, as JSON
+    pub raw: String,
+}
+
+/// Restricts `query` to entries matching every field that is set
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Recorded at or after this date/time (ex: 2021-01-01)
+    pub since: Option<String>,
+    /// Recorded before this date/time
+    pub until: Option<String>,
+    pub product: Option<String>,
+    pub kind: Option<Kind>,
+}
+
+pub fn ledger_path() -> PathBuf {
+    hodl_home().join("ledger.sqlite3")
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// The response as Coinbase sent it, or as hodl parsed it if it didn't come straight
+/// from a response
+pub fn raw<T: serde::Serialize>(response: &T, body: &RawBody) -> String {
+    match &body.0 {
+        Some(b) => b.clone(),
+        None => serde_json::to_string(response).expect("API responses are always serializable"),
+    }
+}
+
+fn entry(kind: Kind, exchange_id: &str, product: &str, amount: &str, status: &str) -> Entry {
+    Entry {
+        recorded_at: String::new(),
+        updated_at: String::new(),
+        kind,
+        exchange_id: String::from(exchange_id),
+        product: String::from(product),
+        side: None,
+        amount: String::from(amount),
+        size: None,
+        fee: None,
+        status: String::from(status),
+        raw: String::new(),
+    }
+}
+
+pub fn order_entry(order: &Order) -> Entry {
+    // Until it fills, an order's amount is what it was placed with
+    let amount = if order.executed_value.parse::<f64>().unwrap_or(0.0) > 0.0 {
+        order.executed_value.clone()
+    } else {
+        order
+            .funds
+            .clone()
+            .or_else(|| order.specified_funds.clone())
+            .unwrap_or_else(|| String::from("0"))
+    };
+    let status = if order.settled {
+        String::from("settled")
+    } else {
+        order.status.clone()
+    };
+    Entry {
+        side: Some(order.side.clone()),
+        size: Some(order.filled_size.clone()),
+        fee: Some(order.fill_fees.clone()),
+        raw: raw(order, &order.raw),
+        ..entry(Kind::Order, &order.id, &order.product_id, &amount, &status)
+    }
+}
+
+pub fn deposit_entry(kind: Kind, deposit: &DepositResponse) -> Entry {
+    Entry {
+        raw: raw(deposit, &deposit.raw),
+        ..entry(
+            kind,
+            &deposit.id,
+            &deposit.currency,
+            &deposit.amount,
+            "created",
+        )
+    }
+}
+
+pub fn withdrawal_entry(withdrawal: &WithdrawalResponse) -> Entry {
+    Entry {
+        fee: withdrawal.fee.clone(),
+        raw: raw(withdrawal, &withdrawal.raw),
+        ..entry(
+            Kind::Withdrawal,
+            &withdrawal.id,
+            &withdrawal.currency,
+            &withdrawal.amount,
+            "created",
+        )
+    }
+}
+
+pub fn conversion_entry(conversion: &Conversion) -> Entry {
+    let product = format!("{}-{}", conversion.from, conversion.to);
+    Entry {
+        raw: raw(conversion, &conversion.raw),
+        ..entry(
+            Kind::Conversion,
+            &conversion.id,
+            &product,
+            &conversion.amount,
+            "completed",
+        )
+    }
+}
+
+fn init(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS entries (
+            id INTEGER PRIMARY KEY,
+            recorded_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            exchange_id TEXT NOT NULL,
+            product TEXT NOT NULL,
+            side TEXT,
+            amount TEXT NOT NULL,
+            size TEXT,
+            fee TEXT,
+            status TEXT NOT NULL,
+            raw TEXT NOT NULL,
+            UNIQUE (kind, exchange_id)
+        );
+        CREATE INDEX IF NOT EXISTS entries_recorded_at ON entries (recorded_at);",
+    )
+    .map_err(|e| format!("Failed to set up the ledger: {}", e))
+}
+
+pub fn open_at(path: &Path) -> Result<Connection, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let conn =
+        Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    init(&conn)?;
+    Ok(conn)
+}
+
+pub fn open() -> Result<Connection, String> {
+    open_at(&ledger_path())
+}
+
+/// Add `entry` to the ledger, or bring it up to date if it's already there
+pub fn insert(conn: &Connection, entry: &Entry, at: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO entries
+            (recorded_at, updated_at, kind, exchange_id, product, side, amount, size, fee, status, raw)
+         VALUES (?1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+         ON CONFLICT (kind, exchange_id) DO UPDATE SET
+            updated_at = ?1, amount = ?6, size = ?7, fee = ?8, status = ?9, raw = ?10",
+        params![
+            at,
+            entry.kind.as_str(),
+            entry.exchange_id,
+            entry.product,
+            entry.side,
+            entry.amount,
+            entry.size,
+            entry.fee,
+            entry.status,
+            entry.raw,
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to record {} {}: {}", entry.kind.as_str(), entry.exchange_id, e))
+}
+
+/// Bring `entry` up to date if it's in the ledger, returning whether it was
+pub fn update(conn: &Connection, entry: &Entry, at: &str) -> Result<bool, String> {
+    conn.execute(
+        "UPDATE entries SET updated_at = ?1, amount = ?2, size = ?3, fee = ?4, status = ?5, raw = ?6
+         WHERE kind = ?7 AND exchange_id = ?8",
+        params![
+            at,
+            entry.amount,
+            entry.size,
+            entry.fee,
+            entry.status,
+            entry.raw,
+            entry.kind.as_str(),
+            entry.exchange_id,
+        ],
+    )
+    .map(|n| n > 0)
+    .map_err(|e| format!("Failed to update {} {}: {}", entry.kind.as_str(), entry.exchange_id, e))
+}
+
+/// Set the status of an entry already in the ledger, returning whether it was there
+pub fn set_status(
+    conn: &Connection,
+    kind: Kind,
+    exchange_id: &str,
+    status: &str,
+    at: &str,
+) -> Result<bool, String> {
+    conn.execute(
+        "UPDATE entries SET updated_at = ?1, status = ?2 WHERE kind = ?3 AND exchange_id = ?4",
+        params![at, status, kind.as_str(), exchange_id],
+    )
+    .map(|n| n > 0)
+    .map_err(|e| format!("Failed to update {} {}: {}", kind.as_str(), exchange_id, e))
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<Entry> {
+    let kind: String = row.get("kind")?;
+    Ok(Entry {
+        recorded_at: row.get("recorded_at")?,
+        updated_at: row.get("updated_at")?,
+        // Only `insert` writes kinds, so they always parse
+        kind: kind.parse().unwrap_or(Kind::Order),
+        exchange_id: row.get("exchange_id")?,
+        product: row.get("product")?,
+        side: row.get("side")?,
+        amount: row.get("amount")?,
+        size: row.get("size")?,
+        fee: row.get("fee")?,
+        status: row.get("status")?,
+        raw: row.get("raw")?,
+    })
+}
+
+/// The entries matching `filter`, oldest first
+pub fn query(conn: &Connection, filter: &Filter) -> Result<Vec<Entry>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT * FROM entries
+             WHERE (?1 IS NULL OR recorded_at >= ?1)
+               AND (?2 IS NULL OR recorded_at < ?2)
+               AND (?3 IS NULL OR product = ?3)
+               AND (?4 IS NULL OR kind = ?4)
+             ORDER BY recorded_at, id",
+        )
+        .map_err(|e| format!("Failed to query the ledger: {}", e))?;
+    let rows = stmt
+        .query_map(
+            params![
+                filter.since,
+                filter.until,
+                filter.product,
+                filter.kind.map(|k| k.as_str()),
+            ],
+            from_row,
+        )
+        .map_err(|e| format!("Failed to query the ledger: {}", e))?;
+    rows.collect::<rusqlite::Result<Vec<Entry>>>()
+        .map_err(|e| format!("Failed to read the ledger: {}", e))
+}
+
+pub fn get(conn: &Connection, kind: Kind, exchange_id: &str) -> Result<Option<Entry>, String> {
+    conn.query_row(
+        "SELECT * FROM entries WHERE kind = ?1 AND exchange_id = ?2",
+        params![kind.as_str(), exchange_id],
+        from_row,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to query the ledger: {}", e))
+}
+
+/// One line per entry, for reading in a terminal
+pub fn format_entry(entry: &Entry) -> String {
+    format!(
+        "{}  {:<15}  {:<9}  {:<4}  {:>14}  {:<9}  {}",
+        entry.recorded_at,
+        entry.kind.as_str(),
+        entry.product,
+        entry.side.as_deref().unwrap_or(""),
+        entry.amount,
+        entry.status,
+        entry.exchange_id
+    )
+}
+
+/// Print `entries` to stdout as a table, CSV or a JSON array
+pub fn print(entries: &[Entry], format: &str) -> Result<(), String> {
+    match format {
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for entry in entries {
+                writer
+                    .serialize(entry)
+                    .map_err(|e| format!("Failed to write CSV: {}", e))?;
+            }
+            writer
+                .flush()
+                .map_err(|e| format!("Failed to write CSV: {}", e))
+        }
+        "json" => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(entries).expect("Entries are always serializable")
+            );
+            Ok(())
+        }
+        _ => {
+            for entry in entries {
+                println!("{}", format_entry(entry));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Record a request that moved money. The request has already happened, so a
+/// failure to record it is reported but otherwise ignored.
+pub fn record(entry: Entry) {
+    if let Err(e) = open().and_then(|conn| insert(&conn, &entry, &now())) {
+        logging::warn(
+            &e,
+            fields()
+                .product(&entry.product)
+                .status(&entry.status)
+                .error(ErrorKind::Storage),
+        );
+    }
+}
+
+/// Bring an entry already in the ledger up to date (ex: an order that has since filled)
+pub fn record_update(entry: Entry) {
+    if let Err(e) = open().and_then(|conn| update(&conn, &entry, &now())) {
+        logging::warn(
+            &e,
+            fields()
+                .product(&entry.product)
+                .status(&entry.status)
+                .error(ErrorKind::Storage),
+        );
+    }
+}
+
+/// Set the status of an entry already in the ledger (ex: a cancelled order that
+/// the exchange no longer reports)
+pub fn record_status(kind: Kind, exchange_id: &str, status: &str) {
+    if let Err(e) = open().and_then(|conn| set_status(&conn, kind, exchange_id, status, &now())) {
+        logging::warn(&e, fields().status(status).error(ErrorKind::Storage));
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,109 @@
+use super::*;
+
+fn order(status: &str, executed_value: &str, settled: bool) -> Order {
+    serde_json::from_value(serde_json::json!({
+        "id": "d0c5340b-6d6c-49d9-b567-48c4bfca13d2",
+        "product_id": "BTC-USD",
+        "side": "buy",
+        "funds": "9.9750623400000000",
+        "specified_funds": "10.0000000000000000",
+        "type": "market",
+        "post_only": false,
+        "created_at": "2021-01-10T00:07:00.123Z",
+        "fill_fees": "0.0498753117",
+        "filled_size": "0.00026",
+        "executed_value": executed_value,
+        "status": status,
+        "settled": settled
+    }))
+    .unwrap()
+}
+
+fn deposit(id: &str) -> Entry {
+    entry(Kind::Deposit, id, "USD", "39.00", "created")
+}
+
+#[test]
+fn test_order_entry() {
+    let pending = order_entry(&order("pending", "0.0000000000000000", false));
+    assert_eq!(pending.amount, "9.9750623400000000");
+    assert_eq!(pending.status, "pending");
+    assert_eq!(pending.product, "BTC-USD");
+
+    let settled = order_entry(&order("done", "9.9251870283", true));
+    assert_eq!(settled.amount, "9.9251870283");
+    assert_eq!(settled.status, "settled");
+    assert_eq!(settled.fee, Some(String::from("0.0498753117")));
+}
+
+#[test]
+fn test_insert_and_update() {
+    let conn = Connection::open_in_memory().unwrap();
+    init(&conn).unwrap();
+
+    let placed = order_entry(&order("pending", "0", false));
+    insert(&conn, &placed, "2021-01-10T00:07:00Z").unwrap();
+    let filled = order_entry(&order("done", "9.9251870283", true));
+    assert!(update(&conn, &filled, "2021-01-10T00:07:05Z").unwrap());
+
+    let stored = get(&conn, Kind::Order, &filled.exchange_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.recorded_at, "2021-01-10T00:07:00Z");
+    assert_eq!(stored.updated_at, "2021-01-10T00:07:05Z");
+    assert_eq!(stored.status, "settled");
+    assert_eq!(stored.amount, "9.9251870283");
+
+    // Only entries hodl recorded are updated
+    let mut other = filled.clone();
+    other.exchange_id = String::from("someone-elses-order");
+    assert!(!update(&conn, &other, "2021-01-10T00:08:00Z").unwrap());
+    assert!(set_status(&conn, Kind::Order, &filled.exchange_id, "canceled", "x").unwrap());
+    assert_eq!(query(&conn, &Filter::default()).unwrap().len(), 1);
+}
+
+#[test]
+fn test_query() {
+    let conn = Connection::open_in_memory().unwrap();
+    init(&conn).unwrap();
+    insert(&conn, &deposit("a"), "2021-01-01T00:00:00Z").unwrap();
+    insert(&conn, &deposit("b"), "2021-01-04T00:00:00Z").unwrap();
+    insert(
+        &conn,
+        &order_entry(&order("done", "10", true)),
+        "2021-01-05T00:07:00Z",
+    )
+    .unwrap();
+
+    let ids = |filter: Filter| -> Vec<String> {
+        query(&conn, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.exchange_id)
+            .collect()
+    };
+    assert_eq!(ids(Filter::default()).len(), 3);
+    assert_eq!(
+        ids(Filter {
+            since: Some(String::from("2021-01-02")),
+            until: Some(String::from("2021-01-05")),
+            ..Filter::default()
+        }),
+        vec!["b"]
+    );
+    assert_eq!(
+        ids(Filter {
+            kind: Some(Kind::Deposit),
+            ..Filter::default()
+        }),
+        vec!["a", "b"]
+    );
+    assert_eq!(
+        ids(Filter {
+            product: Some(String::from("BTC-USD")),
+            ..Filter::default()
+        })
+        .len(),
+        1
+    );
+}
@@ -0,0 +1,144 @@
+use crate::config::{hodl_home, profile};
+use crate::logging::{self, fields};
+use chrono::Utc;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+// How long to wait for another hodl to finish before giving up; long enough for a
+// maker buy that reprices until its deadline
+static WAIT_SECS: u64 = 3600;
+static POLL_MILLIS: u64 = 1000;
+
+/// Who holds a lock, as written to the lock file
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Holder {
+    pub pid: u32,
+    pub command: String,
+    /// RFC 3339
+    pub acquired_at: String,
+}
+
+/// A held lock. The OS releases it when the file is closed, on drop or when the
+/// process exits, so a hodl that dies never leaves a lock behind
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+// The lock held by main until it exits
+static HELD: Mutex<Option<Lock>> = Mutex::new(None);
+
+pub fn lock_path() -> PathBuf {
+    hodl_home().join(format!("{}.lock", profile()))
+}
+
+fn read_holder(path: &Path) -> Option<Holder> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Take the lock at `path` if it's free, returning who holds it otherwise
+pub fn try_acquire_at(path: &Path, command: &str) -> Result<Result<Lock, Holder>, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    // The file is never removed: whoever opened it before the removal could still
+    // lock it while someone else locks its replacement
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    match file.try_lock() {
+        Ok(_) => {}
+        Err(TryLockError::WouldBlock) => {
+            // The holder writes itself in just after taking the lock, so it may not be there yet
+            return Ok(Err(read_holder(path).unwrap_or_else(|| Holder {
+                pid: 0,
+                command: String::from("another hodl"),
+                acquired_at: String::from("just now"),
+            })));
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(format!("Failed to lock {}: {}", path.display(), e))
+        }
+    }
+    let holder = Holder {
+        pid: std::process::id(),
+        command: String::from(command),
+        acquired_at: Utc::now().to_rfc3339(),
+    };
+    let json = serde_json::to_string(&holder).expect("Holders are serializable");
+    file.set_len(0)
+        .and_then(|_| file.write_all(json.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Ok(Lock { _file: file }))
+}
+
+/// Take the lock at `path`, waiting up to `wait` for whoever holds it to finish
+pub async fn acquire_at(
+    path: &Path,
+    command: &str,
+    wait: Duration,
+    poll: Duration,
+) -> Result<Lock, String> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let holder = match try_acquire_at(path, command)? {
+            Ok(lock) => return Ok(lock),
+            Err(h) => h,
+        };
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "Timed out waiting for {} (pid {}) to release {}",
+                holder.command,
+                holder.pid,
+                path.display()
+            ));
+        }
+        sleep(poll).await;
+    }
+}
+
+/// Take the profile's lock, so only one hodl at a time moves its money
+pub async fn acquire(command: &str) -> Result<Lock, String> {
+    let path = lock_path();
+    match try_acquire_at(&path, command)? {
+        Ok(lock) => return Ok(lock),
+        Err(holder) => logging::info(
+            &format!(
+                "Waiting for {} (pid {}, since {}) to finish",
+                holder.command, holder.pid, holder.acquired_at
+            ),
+            fields(),
+        ),
+    }
+    acquire_at(
+        &path,
+        command,
+        Duration::from_secs(WAIT_SECS),
+        Duration::from_millis(POLL_MILLIS),
+    )
+    .await
+}
+
+/// Take the profile's lock until `release` is called or the process exits through main
+pub async fn hold(command: &str) -> Result<(), String> {
+    let lock = acquire(command).await?;
+    *HELD.lock().unwrap_or_else(|e| e.into_inner()) = Some(lock);
+    Ok(())
+}
+
+pub fn release() {
+    HELD.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+use super::*;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn test_try_acquire() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("default.lock");
+    let lock = try_acquire_at(&path, "hodl buy").unwrap().unwrap();
+
+    let holder = try_acquire_at(&path, "hodl sweep").unwrap().unwrap_err();
+    assert_eq!(holder.pid, std::process::id());
+    assert_eq!(holder.command, "hodl buy");
+
+    drop(lock);
+    assert!(try_acquire_at(&path, "hodl sweep").unwrap().is_ok());
+    // Nothing is left behind but the lock file itself
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_released_lock_taken_over() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("default.lock");
+    // A holder that has exited leaves its details in the file, but not its OS lock
+    let lock = try_acquire_at(&path, "hodl withdraw-fiat")
+        .unwrap()
+        .unwrap();
+    drop(lock);
+    let lock = try_acquire_at(&path, "hodl buy").unwrap().unwrap();
+    assert_eq!(read_holder(&path).unwrap().command, "hodl buy");
+    drop(lock);
+
+    // Nor does one that died while writing them
+    fs::write(&path, "{\"pid\":").unwrap();
+    let _lock = try_acquire_at(&path, "hodl sweep").unwrap().unwrap();
+    assert_eq!(read_holder(&path).unwrap().command, "hodl sweep");
+}
+
+#[test]
+fn test_racing_takeovers_of_a_released_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("default.lock");
+    for _ in 0..50 {
+        drop(
+            try_acquire_at(&path, "hodl withdraw-fiat")
+                .unwrap()
+                .unwrap(),
+        );
+        let barrier = Arc::new(Barrier::new(2));
+        let racers: Vec<_> = ["hodl sweep", "hodl buy"]
+            .iter()
+            .map(|command| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    try_acquire_at(&path, command).unwrap()
+                })
+            })
+            .collect();
+        // Both results are held until both racers are done, so neither lock is released early
+        let results: Vec<_> = racers.into_iter().map(|r| r.join().unwrap()).collect();
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_acquire_at_times_out() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("default.lock");
+    let _lock = try_acquire_at(&path, "hodl buy").unwrap().unwrap();
+    let result = acquire_at(
+        &path,
+        "hodl sweep",
+        Duration::from_millis(50),
+        Duration::from_millis(10),
+    )
+    .await;
+    assert!(result
+        .unwrap_err()
+        .starts_with("Timed out waiting for hodl buy"));
+}
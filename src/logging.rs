@@ -0,0 +1,323 @@
+use chrono::{Local, SecondsFormat};
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// Rotate the log file once it reaches 10 MiB, keeping the last 5
+static DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+static DEFAULT_KEEP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    /// Everything, including the full responses from the exchange
+    Debug,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("'{}' is not one of error, warn, info or debug", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One line per event for reading: time, level, message, then key=value fields
+    Text,
+    /// One JSON object per line for parsing
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("'{}' is not one of text or json", s)),
+        }
+    }
+}
+
+/// Why something failed, for grouping errors without parsing their messages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// Missing or invalid settings, such as unset API credentials
+    Config,
+    /// hodl's own files (state, ledger, metrics) couldn't be read or written
+    Storage,
+    /// Another hodl held the profile's lock for too long
+    Locked,
+    /// The request couldn't be built
+    InvalidRequest,
+    /// The request never got a response, or the response couldn't be read
+    Http,
+    /// The exchange responded with an error message
+    Api,
+    /// The exchange responded with something other than what was asked for
+    Unexpected,
+    /// A guardrail or check refused to go ahead
+    Refused,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Config => "config",
+            ErrorKind::Storage => "storage",
+            ErrorKind::Locked => "locked",
+            ErrorKind::InvalidRequest => "invalid-request",
+            ErrorKind::Http => "http",
+            ErrorKind::Api => "api",
+            ErrorKind::Unexpected => "unexpected",
+            ErrorKind::Refused => "refused",
+        }
+    }
+}
+
+/// The fields an event may carry, named the same way everywhere so logs can be filtered on them
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Fields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+}
+
+impl Fields {
+    pub fn product(mut self, product: &str) -> Fields {
+        self.product = Some(String::from(product));
+        self
+    }
+
+    pub fn amount(mut self, amount: impl ToString) -> Fields {
+        self.amount = Some(amount.to_string());
+        self
+    }
+
+    pub fn order_id(mut self, order_id: &str) -> Fields {
+        self.order_id = Some(String::from(order_id));
+        self
+    }
+
+    pub fn status(mut self, status: &str) -> Fields {
+        self.status = Some(String::from(status));
+        self
+    }
+
+    pub fn error(mut self, kind: ErrorKind) -> Fields {
+        self.error_kind = Some(kind);
+        self
+    }
+}
+
+pub fn fields() -> Fields {
+    Fields::default()
+}
+
+/// The `[log]` section of the config; `--log-level`, `--log-format` and `--log-file`
+/// override it
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogSettings {
+    pub level: Option<Level>,
+    pub format: Option<Format>,
+    /// Append to this file instead of writing to stderr
+    pub file: Option<PathBuf>,
+    /// Rotate the file once it would grow past this size
+    pub max_bytes: Option<u64>,
+    /// How many rotated files (hodl.log.1, hodl.log.2, ...) to keep
+    pub keep: Option<usize>,
+}
+
+struct Logger {
+    level: Level,
+    format: Format,
+    file: Option<PathBuf>,
+    max_bytes: u64,
+    keep: usize,
+    /// The subcommand being run, added to every event
+    command: Option<String>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    level: Level::Info,
+    format: Format::Text,
+    file: None,
+    max_bytes: DEFAULT_MAX_BYTES,
+    keep: DEFAULT_KEEP,
+    command: None,
+});
+
+pub fn init(settings: &LogSettings, command: &str) {
+    let mut logger = LOGGER.lock().unwrap_or_else(|e| e.into_inner());
+    logger.level = settings.level.unwrap_or(Level::Info);
+    logger.format = settings.format.unwrap_or(Format::Text);
+    logger.file = settings.file.clone();
+    logger.max_bytes = settings.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+    logger.keep = settings.keep.unwrap_or(DEFAULT_KEEP);
+    logger.command = Some(String::from(command)).filter(|c| !c.is_empty());
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: &'a str,
+    level: &'static str,
+    message: &'a str,
+    #[serde(flatten)]
+    fields: &'a Fields,
+}
+
+pub fn format_event(
+    format: Format,
+    time: &str,
+    level: Level,
+    message: &str,
+    fields: &Fields,
+) -> String {
+    match format {
+        Format::Json => serde_json::to_string(&Record {
+            time,
+            level: level.as_str(),
+            message,
+            fields,
+        })
+        .expect("Log records are always serializable"),
+        Format::Text => {
+            let mut line = format!(
+                "[{}] {:<5} {}",
+                time,
+                level.as_str().to_uppercase(),
+                message
+            );
+            let error_kind = fields.error_kind.map(|k| String::from(k.as_str()));
+            let pairs = [
+                ("command", &fields.command),
+                ("product", &fields.product),
+                ("amount", &fields.amount),
+                ("order_id", &fields.order_id),
+                ("status", &fields.status),
+                ("error_kind", &error_kind),
+            ];
+            for (key, value) in pairs.iter() {
+                if let Some(v) = value {
+                    if v.is_empty() || v.contains(char::is_whitespace) {
+                        line.push_str(&format!(" {}={:?}", key, v));
+                    } else {
+                        line.push_str(&format!(" {}={}", key, v));
+                    }
+                }
+            }
+            line
+        }
+    }
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+/// Shift hodl.log to hodl.log.1, hodl.log.1 to hodl.log.2 and so on, dropping the
+/// oldest beyond `keep`
+pub fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let ignore_missing = |r: io::Result<()>| match r {
+        Err(e) if e.kind() == IoErrorKind::NotFound => Ok(()),
+        r => r,
+    };
+    if keep == 0 {
+        return ignore_missing(fs::remove_file(path));
+    }
+    ignore_missing(fs::remove_file(rotated(path, keep)))?;
+    for n in (1..keep).rev() {
+        ignore_missing(fs::rename(rotated(path, n), rotated(path, n + 1)))?;
+    }
+    ignore_missing(fs::rename(path, rotated(path, 1)))
+}
+
+/// Append `line` to the log file at `path`, rotating it first if the line would
+/// take it past `max_bytes`
+pub fn append(path: &Path, line: &str, max_bytes: u64, keep: usize) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if size > 0 && size + line.len() as u64 + 1 > max_bytes {
+        rotate(path, keep)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // One write per line, so lines from concurrent hodl processes don't interleave
+    file.write_all(format!("{}\n", line).as_bytes())
+}
+
+pub fn log(level: Level, message: &str, fields: Fields) {
+    let logger = LOGGER.lock().unwrap_or_else(|e| e.into_inner());
+    if level > logger.level {
+        return;
+    }
+    let fields = Fields {
+        command: fields.command.or_else(|| logger.command.clone()),
+        ..fields
+    };
+    let time = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+    let line = format_event(logger.format, &time, level, message, &fields);
+    if let Some(path) = &logger.file {
+        match append(path, &line, logger.max_bytes, logger.keep) {
+            Ok(_) => return,
+            Err(e) => eprintln!("Failed to write to {}: {}", path.display(), e),
+        }
+    }
+    eprintln!("{}", line);
+}
+
+pub fn error(message: &str, fields: Fields) {
+    log(Level::Error, message, fields)
+}
+
+pub fn warn(message: &str, fields: Fields) {
+    log(Level::Warn, message, fields)
+}
+
+pub fn info(message: &str, fields: Fields) {
+    log(Level::Info, message, fields)
+}
+
+pub fn debug(message: &str, fields: Fields) {
+    log(Level::Debug, message, fields)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,78 @@
+use super::*;
+use tempfile::tempdir;
+
+#[test]
+fn test_levels() {
+    assert!(Level::Error < Level::Warn && Level::Info < Level::Debug);
+    assert_eq!("warn".parse::<Level>(), Ok(Level::Warn));
+    assert!("verbose".parse::<Level>().is_err());
+    assert_eq!("json".parse::<Format>(), Ok(Format::Json));
+}
+
+#[test]
+fn test_format_event() {
+    let placed = fields()
+        .product("BTC-USD")
+        .amount(8.5)
+        .order_id("d0c5340b")
+        .status("pending");
+    let placed = Fields {
+        command: Some(String::from("buy")),
+        ..placed
+    };
+    let time = "2021-01-10T12:00:00.000+00:00";
+    assert_eq!(
+        format_event(Format::Text, time, Level::Info, "Market order placed", &placed),
+        "[2021-01-10T12:00:00.000+00:00] INFO  Market order placed command=buy product=BTC-USD amount=8.5 order_id=d0c5340b status=pending"
+    );
+    assert_eq!(
+        format_event(
+            Format::Json,
+            time,
+            Level::Info,
+            "Market order placed",
+            &placed
+        ),
+        r#"{"time":"2021-01-10T12:00:00.000+00:00","level":"info","message":"Market order placed","command":"buy","product":"BTC-USD","amount":"8.5","order_id":"d0c5340b","status":"pending"}"#
+    );
+
+    let failed = fields().product("BTC-USD").error(ErrorKind::InvalidRequest);
+    assert_eq!(
+        format_event(Format::Text, time, Level::Error, "Failed", &failed),
+        "[2021-01-10T12:00:00.000+00:00] ERROR Failed product=BTC-USD error_kind=invalid-request"
+    );
+    assert_eq!(
+        format_event(Format::Json, time, Level::Error, "Failed", &failed),
+        r#"{"time":"2021-01-10T12:00:00.000+00:00","level":"error","message":"Failed","product":"BTC-USD","error_kind":"invalid-request"}"#
+    );
+    let spaced = fields().status("in progress");
+    assert!(format_event(Format::Text, time, Level::Warn, "Hm", &spaced)
+        .ends_with(" status=\"in progress\""));
+}
+
+#[test]
+fn test_append_rotates() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("hodl.log");
+    // Each line is 7 bytes with its newline, so every third line rotates
+    for i in 0..8 {
+        append(&path, &format!("line {}", i), 14, 2).unwrap();
+    }
+    let read = |p: &Path| fs::read_to_string(p).unwrap();
+    assert_eq!(read(&path), "line 6\nline 7\n");
+    assert_eq!(read(&rotated(&path, 1)), "line 4\nline 5\n");
+    assert_eq!(read(&rotated(&path, 2)), "line 2\nline 3\n");
+    assert!(!rotated(&path, 3).exists());
+}
+
+#[test]
+fn test_rotate_keep_none() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("hodl.log");
+    fs::write(&path, "old\n").unwrap();
+    rotate(&path, 0).unwrap();
+    assert!(!path.exists());
+    assert!(!rotated(&path, 1).exists());
+    // Nothing to rotate yet is fine
+    rotate(&path, 3).unwrap();
+}
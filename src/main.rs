@@ -10,17 +10,70 @@ extern crate serde_derive;
 extern crate url;
 
 use chrono::{Duration, Local};
-use clap::{App, Arg, SubCommand};
-use csv::Writer;
+use clap::{App, Arg, ArgGroup, SubCommand};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::OnceLock;
 use std::{env, io};
 
 pub mod api;
+pub mod book;
+pub mod buy;
+pub mod config;
+pub mod daemon;
+pub mod execution;
+pub mod export;
+pub mod fees;
+pub mod fiat;
+pub mod history;
+pub mod ledger;
+pub mod lock;
+pub mod logging;
+pub mod market;
+pub mod metrics;
+pub mod notify;
+pub mod ratelimit;
+pub mod reconcile;
+pub mod schedule;
+pub mod state;
+pub mod stop;
+pub mod strategy;
+pub mod sweep;
+pub mod transfers;
+pub mod watch;
+pub mod withdraw;
 use api::{
-    get_history, get_tick, list_orders, make_deposit, place_order, print_balance,
-    print_payment_methods, request_report, ApiResponse,
+    convert, get_book, get_fees, get_stats, get_tick, list_currencies, list_fills, list_orders,
+    list_products, list_trades, list_transfers, make_deposit, print_balance, print_payment_methods,
+    request_report, withdraw_fiat,
 };
+use buy::{buy, BuyOptions, BuyOutcome, EXIT_INSUFFICIENT_FUNDS};
+use execution::{Fallback, MakerOptions};
+use export::{Destination, Format, TimeFormat};
+use fees::{format_rate, summarize_fills};
+use logging::{fields, ErrorKind};
+use notify::{check_balances, notify, summarize_orders, Event, EventKind};
+use stop::{place_stop, StopKind};
+use strategy::{parse_bands, MovingAverageOptions};
+use sweep::sweep;
+use transfers::{format_transfer, wait_for_transfer, TransferStatus};
+use withdraw::withdraw;
 
 static DEFAULT_PRODUCT: &str = "BTC-USD";
+// The subcommand being run, for the metrics written on exit
+static COMMAND: OnceLock<String> = OnceLock::new();
+// Commands that move money hold the profile's lock while they run, so overlapping
+// runs (ex: from cron) take turns instead of racing for the same balance
+static LOCKED_COMMANDS: &[&str] = &[
+    "buy",
+    "stop-sell",
+    "stop-buy",
+    "deposit",
+    "withdraw-fiat",
+    "convert",
+    "withdraw",
+    "sweep",
+];
 
 #[tokio::main]
 async fn main() {
@@ -28,6 +81,29 @@ async fn main() {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::with_name("log-level")
+                .help("Least severe log events to write (default: info, or [log] level in the config)")
+                .long("log-level")
+                .takes_value(true)
+                .possible_values(&["error", "warn", "info", "debug"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .help("Write log events as text, or as one JSON object per line")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .help("Append log events to this file instead of stderr, rotating it as it grows")
+                .long("log-file")
+                .takes_value(true)
+                .global(true),
+        )
         .subcommand(
             SubCommand::with_name("balance")
                 .about("Check balance(s).")
@@ -53,6 +129,148 @@ async fn main() {
                     Arg::with_name("amount")
                         .help("The amount, in USD, you wish to purchase (ex: 5.25")
                         .index(2),
+                )
+                .arg(
+                    Arg::with_name("strategy")
+                        .help("How to size the purchase: a fixed amount, or scaled by the moving average")
+                        .long("strategy")
+                        .possible_values(&["fixed", "ma"])
+                        .default_value("fixed"),
+                )
+                .arg(
+                    Arg::with_name("ma-days")
+                        .help("Number of daily candles in the moving average (max 300)")
+                        .long("ma-days")
+                        .default_value(strategy::DEFAULT_MA_DAYS),
+                )
+                .arg(
+                    Arg::with_name("bands")
+                        .help("Comma-separated deviation%:multiplier pairs (ex: -20:1.5,20:0.5)")
+                        .long("bands")
+                        .allow_hyphen_values(true)
+                        .default_value(strategy::DEFAULT_BANDS),
+                )
+                .arg(
+                    Arg::with_name("min-amount")
+                        .help("Never buy less than this many USD when scaling")
+                        .long("min-amount")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-amount")
+                        .help("Never buy more than this many USD when scaling")
+                        .long("max-amount")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("execution")
+                        .help("Take liquidity with a market order, or post a maker bid first")
+                        .long("execution")
+                        .possible_values(&["market", "maker"])
+                        .default_value("market"),
+                )
+                .arg(
+                    Arg::with_name("attempts")
+                        .help("How many times to (re-)price the maker bid")
+                        .long("attempts")
+                        .default_value(execution::DEFAULT_ATTEMPTS),
+                )
+                .arg(
+                    Arg::with_name("reprice-secs")
+                        .help("Seconds to wait for a maker bid to fill before re-pricing it")
+                        .long("reprice-secs")
+                        .default_value(execution::DEFAULT_REPRICE_SECS),
+                )
+                .arg(
+                    Arg::with_name("deadline-secs")
+                        .help("Seconds after which to stop bidding and fall back")
+                        .long("deadline-secs")
+                        .default_value(execution::DEFAULT_DEADLINE_SECS),
+                )
+                .arg(
+                    Arg::with_name("fallback")
+                        .help("What to do with unfilled funds once the maker attempts run out")
+                        .long("fallback")
+                        .possible_values(&["market", "none"])
+                        .default_value("market"),
+                )
+                .arg(
+                    Arg::with_name("on-insufficient-funds")
+                        .help("When USD hasn't cleared: skip (exit code 3), buy with what's available, queue for the next run, or deposit the shortfall and queue. Defaults to the config's [buy] setting, or skip")
+                        .long("on-insufficient-funds")
+                        .possible_values(&["skip", "partial", "queue", "deposit"])
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stop-sell")
+                .about("Place a stop-loss order that sells once the price falls to the stop price")
+                .arg(
+                    Arg::with_name("currency")
+                        .help("The currency you wish to sell for USD (ex: BTC)")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help("The amount of the currency to sell (ex: 0.1)")
+                        .long("size")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("stop-price")
+                        .help("Trigger the order when the price falls to this many USD")
+                        .long("stop-price")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("limit-price")
+                        .help("[optional] place a limit order at this price instead of a market order")
+                        .long("limit-price")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stop-buy")
+                .about("Place a stop-entry order that buys once the price rises to the stop price")
+                .arg(
+                    Arg::with_name("currency")
+                        .help("The currency you wish to purchase with USD (ex: BTC)")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help("The amount of the currency to buy (ex: 0.1)")
+                        .long("size")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("funds")
+                        .help("The amount, in USD, to spend; only for stop orders without a limit price")
+                        .long("funds")
+                        .takes_value(true)
+                        .conflicts_with("limit-price"),
+                )
+                .group(
+                    ArgGroup::with_name("quantity")
+                        .args(&["size", "funds"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("stop-price")
+                        .help("Trigger the order when the price rises to this many USD")
+                        .long("stop-price")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("limit-price")
+                        .help("[optional] place a limit order at this price instead of a market order")
+                        .long("limit-price")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -74,6 +292,106 @@ async fn main() {
                         .index(1),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("products")
+                .about("List the product-ids on the exchange, with their increments and minimums")
+                .arg(
+                    Arg::with_name("quote")
+                        .help("Only list products quoted in this currency (ex: USD)")
+                        .long("quote")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("status")
+                        .help("Only list products with this status (ex: online)")
+                        .long("status")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("currencies")
+                .about("List the currencies on the exchange, with their minimum sizes and status"),
+        )
+        .subcommand(
+            SubCommand::with_name("trades")
+                .about("List the most recent trades for the given product-id")
+                .arg(
+                    Arg::with_name("product-id")
+                        .help("The product-id to check. Defaults to BTC-USD")
+                        .default_value(DEFAULT_PRODUCT)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .help("How many trades to list, newest first")
+                        .long("limit")
+                        .takes_value(true)
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("How to print the trades")
+                        .long("format")
+                        .possible_values(&["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Print the open, high, low, last price and volume over the last 24 hours")
+                .arg(
+                    Arg::with_name("product-id")
+                        .help("The product-id to check. Defaults to BTC-USD")
+                        .default_value(DEFAULT_PRODUCT)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("book")
+                .about("Show the order book for the given product-id, and what a buy would cost")
+                .arg(
+                    Arg::with_name("product-id")
+                        .help("The product-id to check. Defaults to BTC-USD")
+                        .default_value(DEFAULT_PRODUCT)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .help("1: best bid and ask, 2: top 50 price levels, 3: every order")
+                        .long("level")
+                        .takes_value(true)
+                        .possible_values(&["1", "2", "3"])
+                        .default_value("2"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .help("How many rows of bids and asks to show")
+                        .long("depth")
+                        .takes_value(true)
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .help("Estimate the average fill price and slippage of buying this much USD")
+                        .long("amount")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Stream live prices and trades for products until stopped with Ctrl-C")
+                .arg(
+                    Arg::with_name("product-id")
+                        .help("The product-ids to watch (ex: BTC-USD ETH-USD)")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .help("Write each ticker and trade as a line of JSON instead")
+                        .long("json"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("report")
                 .about("Generate a coinbase report for a given account id.")
@@ -113,6 +431,38 @@ async fn main() {
                     Arg::with_name("granularity")
                         .help("Desired timeslice in seconds")
                         .index(4),
+                )
+                .arg(
+                    Arg::with_name("append")
+                        .help("Append to this file instead of printing, resuming after the candles already in it (csv, jsonl or sqlite)")
+                        .long("append")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .help("Write to this file instead of printing; it's only replaced once the download finishes")
+                        .long("out")
+                        .takes_value(true)
+                        .conflicts_with("append"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("How to write the candles; parquet needs --out and sqlite needs --append")
+                        .long("format")
+                        .possible_values(&["csv", "jsonl", "parquet", "sqlite"])
+                        .default_value("csv"),
+                )
+                .arg(
+                    Arg::with_name("time-format")
+                        .help("Write candle times as seconds since the epoch or RFC 3339")
+                        .long("time-format")
+                        .possible_values(&["epoch", "rfc3339"])
+                        .default_value("epoch"),
+                )
+                .arg(
+                    Arg::with_name("report-gaps")
+                        .help("Log the times the exchange has no candles for (no trades, or an outage)")
+                        .long("report-gaps"),
                 ),
         )
         .subcommand(
@@ -122,112 +472,769 @@ async fn main() {
                     Arg::with_name("amount")
                         .help("The amount of USD to deposit into Coinbase Pro")
                         .index(1),
+                )
+                .arg(
+                    Arg::with_name("payment-method")
+                        .help("Id or name of the bank account to deposit from. Defaults to BANK_ID")
+                        .long("payment-method")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .help("Check the deposit without making it")
+                        .long("dry-run"),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .help("Wait for the deposit to complete (or fail) before exiting")
+                        .long("wait"),
+                )
+                .arg(
+                    Arg::with_name("poll-secs")
+                        .help("With --wait, seconds between status checks")
+                        .long("poll-secs")
+                        .default_value(transfers::DEFAULT_POLL_SECS),
+                )
+                .arg(
+                    Arg::with_name("timeout-secs")
+                        .help("With --wait, give up after this many seconds")
+                        .long("timeout-secs")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("transfers")
+                .about("List deposits and withdrawals with their status")
+                .arg(
+                    Arg::with_name("type")
+                        .help("Only list transfers of this type")
+                        .long("type")
+                        .possible_values(&["deposit", "withdraw"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("pending")
+                        .help("Only list transfers that haven't completed or been canceled")
+                        .long("pending"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw-fiat")
+                .about("Withdraw USD from Coinbase Pro to your bank account")
+                .arg(
+                    Arg::with_name("amount")
+                        .help("The amount of USD to withdraw from Coinbase Pro")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("payment-method")
+                        .help("Id or name of the bank account to withdraw to. Defaults to BANK_ID")
+                        .long("payment-method")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .help("Check the withdrawal without making it")
+                        .long("dry-run"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Convert between USD and a stablecoin (ex: USD to USDC)")
+                .arg(
+                    Arg::with_name("from")
+                        .help("The currency to convert from (ex: USD)")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .help("The currency to convert to (ex: USDC)")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .help("The amount to convert (ex: 100)")
+                        .required(true)
+                        .index(3),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw")
+                .about("Withdraw cryptocurrency to an address from the address book in your config")
+                .arg(
+                    Arg::with_name("currency")
+                        .help("The currency to withdraw (ex: BTC)")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .help("The amount of the currency to withdraw (ex: 0.05)")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .help("The name of the destination in the config's address book")
+                        .long("to")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .help("Don't ask for confirmation after showing the fee estimate")
+                        .long("yes")
+                        .short("y"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sweep")
+                .about("Withdraw balances above the thresholds in your config to cold storage")
+                .arg(
+                    Arg::with_name("dry-run")
+                        .help("Show what would be swept without withdrawing anything")
+                        .long("dry-run"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("fees")
+                .about("Show your fee tier, 30-day volume and the fees paid on past fills")
+                .arg(
+                    Arg::with_name("product-id")
+                        .help("The product-id(s) to total fees for. Defaults to BTC-USD")
+                        .default_value(DEFAULT_PRODUCT)
+                        .multiple(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ledger")
+                .about("Show the orders, deposits, withdrawals and conversions hodl has made")
+                .arg(
+                    Arg::with_name("since")
+                        .help("Only show entries recorded on or after this date (ex: 2021-01-01)")
+                        .long("since")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only show entries recorded before this date")
+                        .long("until")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("product")
+                        .help("Only show entries for this product-id (ex: BTC-USD) or currency")
+                        .long("product")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .help("Only show entries of this type")
+                        .long("type")
+                        .possible_values(&[
+                            "order",
+                            "deposit",
+                            "withdrawal",
+                            "fiat-withdrawal",
+                            "conversion",
+                        ])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("How to print the entries")
+                        .long("format")
+                        .possible_values(&["table", "csv", "json"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reconcile")
+                .about("Compare the ledger with the orders, fills and transfers on the exchange")
+                .arg(
+                    Arg::with_name("since")
+                        .help("Only compare activity on or after this date (ex: 2021-01-01)")
+                        .long("since")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only compare activity before this date")
+                        .long("until")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("product")
+                        .help("Also fetch fills for this product-id (ex: BTC-USD)")
+                        .long("product")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("import")
+                        .help("Add entries found on the exchange but not in the ledger")
+                        .long("import"),
+                )
+                .arg(
+                    Arg::with_name("update")
+                        .help("Update mismatched entries in the ledger to match the exchange")
+                        .long("update"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Run the jobs scheduled in your config until stopped with SIGTERM"),
+        )
+        .subcommand(
+            SubCommand::with_name("catch-up")
+                .about("Make up scheduled runs missed while hodl wasn't running, then exit"),
+        )
         .get_matches();
+    let command = COMMAND.get_or_init(|| String::from(matches.subcommand_name().unwrap_or("")));
+    init_logging(&matches, command);
+    if LOCKED_COMMANDS.contains(&command.as_str()) {
+        if let Err(e) = lock::hold(&format!("hodl {}", command)).await {
+            fail(&e, ErrorKind::Locked);
+        }
+    }
 
     if let Some(matches) = matches.subcommand_matches("tick") {
         let product = matches.value_of("product-id").unwrap_or(DEFAULT_PRODUCT);
         if let Some(tick) = get_tick(product).await {
             println!("{} {:#?}", product, tick);
-            std::process::exit(0);
+            exit(0);
         }
-        std::process::exit(1);
+        market::suggest_products(product).await;
+        exit(1);
     }
 
-    if let Some(matches) = matches.subcommand_matches("history") {
+    if let Some(matches) = matches.subcommand_matches("products") {
+        let products = match list_products().await {
+            Some(p) => p,
+            None => exit(1),
+        };
+        let products = market::filter_products(
+            products,
+            matches.value_of("quote"),
+            matches.value_of("status"),
+        );
+        print!("{}", market::format_products(&products));
+        exit(0);
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("currencies") {
+        let mut currencies = match list_currencies().await {
+            Some(c) => c,
+            None => exit(1),
+        };
+        currencies.sort_by(|a, b| a.id.cmp(&b.id));
+        print!("{}", market::format_currencies(&currencies));
+        exit(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("trades") {
         let product = matches.value_of("product-id").unwrap_or(DEFAULT_PRODUCT);
-        let start = matches.value_of("start").unwrap_or("");
-        let end = matches.value_of("end").unwrap_or("");
-        let granularity = matches.value_of("granularity").unwrap_or("");
+        let limit = parse_arg(matches, "limit").unwrap_or(100);
+        let trades = match list_trades(product, limit).await {
+            Some(t) => t,
+            None => {
+                market::suggest_products(product).await;
+                exit(1)
+            }
+        };
+        if let Err(e) = market::print_trades(&trades, matches.value_of("format").unwrap_or("")) {
+            fail(&e, ErrorKind::Unexpected);
+        }
+        exit(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        let product = matches.value_of("product-id").unwrap_or(DEFAULT_PRODUCT);
+        if let Some(stats) = get_stats(product).await {
+            println!("{}", market::format_stats(product, &stats));
+            exit(0);
+        }
+        market::suggest_products(product).await;
+        exit(1);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("book") {
+        let product = matches.value_of("product-id").unwrap_or(DEFAULT_PRODUCT);
+        let level = parse_arg(matches, "level").unwrap_or(2);
+        let depth = parse_arg(matches, "depth").unwrap_or(10);
+        let amount: Option<f64> = parse_arg(matches, "amount");
+        let book = match get_book(product, level).await {
+            Some(b) => b,
+            None => {
+                market::suggest_products(product).await;
+                exit(1)
+            }
+        };
+        println!("{} order book (level {})", product, level);
+        print!("{}", book::format_book(&book, depth));
+        if let Some(amount) = amount {
+            match book::estimate_buy(&book.asks, amount) {
+                Some(estimate) => println!("{}", book::format_estimate(&estimate, amount)),
+                None => println!("There are no asks to buy from"),
+            }
+        }
+        exit(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("watch") {
+        let products = matches
+            .values_of("product-id")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+        let mut options = watch::WatchOptions::new(products);
+        options.json = matches.is_present("json");
+        options.redraw = !options.json && io::stdout().is_terminal();
+        let mut stdout = io::stdout();
+        tokio::select! {
+            result = watch::watch(&options, &mut stdout) => {
+                if let Err(e) = result {
+                    for product in &options.products {
+                        market::suggest_products(product).await;
+                    }
+                    fail(&e, ErrorKind::Api);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        exit(0);
+    }
 
-        let mut wtr = Writer::from_writer(io::stdout());
-        wtr.write_record(["time", "low", "high", "open", "close", "volume"])
-            .expect("Failed to write CSV header");
-        wtr.flush().expect("Failed to flush CSV writer");
+    if let Some(matches) = matches.subcommand_matches("fees") {
+        let fees = match get_fees().await {
+            Some(f) => f,
+            None => exit(1),
+        };
+        println!("Maker fee rate: {}", format_rate(&fees.maker_fee_rate));
+        println!("Taker fee rate: {}", format_rate(&fees.taker_fee_rate));
+        if let Some(volume) = fees.usd_volume {
+            println!("30-day volume: ${}", volume);
+        }
+        for product in matches.values_of("product-id").into_iter().flatten() {
+            let fills = match list_fills(product).await {
+                Some(f) => f,
+                None => exit(1),
+            };
+            let summary = summarize_fills(&fills);
+            println!(
+                "{}: ${:.2} in fees on ${:.2} over {} fills (${:.2} maker, ${:.2} taker)",
+                product,
+                summary.fees,
+                summary.value,
+                summary.fills,
+                summary.maker_fees,
+                summary.taker_fees
+            );
+        }
+        exit(0);
+    }
 
-        if let Err(e) = get_history(product, start, end, granularity, wtr).await {
-            eprintln!("History command failed: {:?}", e);
-            std::process::exit(1);
+    if let Some(matches) = matches.subcommand_matches("history") {
+        let options = history::HistoryOptions {
+            product_id: String::from(matches.value_of("product-id").unwrap_or(DEFAULT_PRODUCT)),
+            start: String::from(matches.value_of("start").unwrap_or("")),
+            end: String::from(matches.value_of("end").unwrap_or("")),
+            granularity: String::from(matches.value_of("granularity").unwrap_or("")),
+            destination: match (matches.value_of("append"), matches.value_of("out")) {
+                (Some(path), _) => Destination::Append(PathBuf::from(path)),
+                (_, Some(path)) => Destination::Replace(PathBuf::from(path)),
+                _ => Destination::Stdout,
+            },
+            format: parse_arg(matches, "format").unwrap_or(Format::Csv),
+            time_format: parse_arg(matches, "time-format").unwrap_or(TimeFormat::Epoch),
+            report_gaps: matches.is_present("report-gaps"),
         };
-        std::process::exit(0);
+        let interrupted = tokio::select! {
+            result = history::download(&options) => {
+                if let Err(e) = result {
+                    logging::error(
+                        &format!("History command failed: {}", e),
+                        fields().error(ErrorKind::Http),
+                    );
+                    market::suggest_products(&options.product_id).await;
+                    exit(1);
+                }
+                false
+            }
+            // Windows are written between requests, so stopping here loses nothing
+            _ = tokio::signal::ctrl_c() => true,
+        };
+        // The download has been dropped by now, and with it the writer, which removes
+        // an unfinished --out file; exit would skip that
+        if interrupted {
+            if let Destination::Append(_) = options.destination {
+                logging::info(
+                    "Interrupted; run the same command again to resume",
+                    fields(),
+                );
+            }
+            exit(130);
+        }
+        exit(0);
     }
 
     if let Some(matches) = matches.subcommand_matches("balance") {
         let currency = matches.value_of("currency");
         print_balance(currency).await;
-        std::process::exit(0);
+        exit(0);
     }
 
     if let Some(_matches) = matches.subcommand_matches("payment-methods") {
         print_payment_methods().await;
-        std::process::exit(0);
+        exit(0);
     }
 
     if let Some(matches) = matches.subcommand_matches("orders") {
         let product_id = matches.value_of("product-id");
         if let Some(orders) = list_orders(product_id).await {
-            println!("{:#?}", orders);
-            std::process::exit(0);
+            let (stops, orders): (Vec<_>, Vec<_>) =
+                orders.into_iter().partition(|o| o.stop.is_some());
+            if !stops.is_empty() {
+                println!("Stop orders: {:#?}", stops);
+            }
+            println!("Orders: {:#?}", orders);
+            exit(0);
         }
-        std::process::exit(1);
+        exit(1);
     }
 
     if let Some(matches) = matches.subcommand_matches("deposit") {
         let amount = match matches.value_of("amount") {
             Some(s) => match s.parse::<f64>() {
                 Ok(a) => a,
-                _ => {
-                    println!("'{}' is an invalid dollar amount", s);
-                    std::process::exit(1);
-                }
+                _ => fail(
+                    &format!("'{}' is an invalid dollar amount", s),
+                    ErrorKind::InvalidRequest,
+                ),
             },
+            None => fail(
+                "You must enter an amount to deposit",
+                ErrorKind::InvalidRequest,
+            ),
+        };
+        let config = load_config();
+        let bank_id = check_fiat_transfer(matches, amount, &config, "deposit").await;
+        let job = format!("deposit {}", amount);
+        let r = match make_deposit(&amount, &bank_id).await {
+            Some(r) => r,
             None => {
-                println!("You must enter an amount to deposit");
-                std::process::exit(1);
+                let event = Event::new(EventKind::Failure, &job, "deposit failed");
+                notify(&config.notify, &event).await;
+                exit(1);
+            }
+        };
+        ledger::record(ledger::deposit_entry(ledger::Kind::Deposit, &r));
+        println!("{:#?}", r);
+        logging::debug(&format!("{:#?}", r), fields());
+        let (event, code) = if matches.is_present("wait") {
+            // Waiting on the bank can take days; don't hold up other runs meanwhile
+            lock::release();
+            let poll_secs = parse_arg(matches, "poll-secs").unwrap_or(600);
+            let timeout_secs = parse_arg(matches, "timeout-secs");
+            match wait_for_transfer(&r.id, poll_secs, timeout_secs).await {
+                Some(t) if transfers::status(&t) == TransferStatus::Completed => {
+                    let message = format!("deposit {} completed", r.id);
+                    logging::info(
+                        &format!("Deposit {} completed", r.id),
+                        fields().product("USD").amount(amount).status("completed"),
+                    );
+                    (Event::new(EventKind::Success, &job, &message), 0)
+                }
+                Some(t) => {
+                    let status = format!("{:?}", transfers::status(&t)).to_lowercase();
+                    let message = format!("deposit {} is {}", r.id, status);
+                    logging::error(
+                        &format!("Deposit {} is {}", r.id, status),
+                        fields()
+                            .product("USD")
+                            .amount(amount)
+                            .status(&status)
+                            .error(ErrorKind::Unexpected),
+                    );
+                    (Event::new(EventKind::Failure, &job, &message), 1)
+                }
+                None => {
+                    let message = format!("could not check on deposit {}", r.id);
+                    (Event::new(EventKind::Failure, &job, &message), 1)
+                }
+            }
+        } else {
+            let message = format!("deposit {} of ${} initiated", r.id, r.amount);
+            (Event::new(EventKind::Success, &job, &message), 0)
+        };
+        notify(&config.notify, &event).await;
+        exit(code);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("transfers") {
+        if let Some(transfers) = list_transfers(matches.value_of("type")).await {
+            let pending_only = matches.is_present("pending");
+            for t in transfers
+                .iter()
+                .filter(|t| !pending_only || !transfers::status(t).is_final())
+            {
+                println!("{}", format_transfer(t));
             }
+            exit(0);
+        }
+        exit(1);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("withdraw-fiat") {
+        let amount = match matches.value_of("amount") {
+            Some(s) => match s.parse::<f64>() {
+                Ok(a) => a,
+                _ => fail(
+                    &format!("'{}' is an invalid dollar amount", s),
+                    ErrorKind::InvalidRequest,
+                ),
+            },
+            None => fail(
+                "You must enter an amount to withdraw",
+                ErrorKind::InvalidRequest,
+            ),
+        };
+        let config = load_config();
+        let bank_id = check_fiat_transfer(matches, amount, &config, "withdraw").await;
+        let job = format!("withdraw-fiat {}", amount);
+        let (event, code) = match withdraw_fiat(&amount, &bank_id).await {
+            Some(r) => {
+                ledger::record(ledger::deposit_entry(ledger::Kind::FiatWithdrawal, &r));
+                println!("{:#?}", r);
+                logging::debug(&format!("{:#?}", r), fields());
+                let message = format!("withdrawal {} of ${} initiated", r.id, r.amount);
+                (Event::new(EventKind::Success, &job, &message), 0)
+            }
+            None => (Event::new(EventKind::Failure, &job, "withdrawal failed"), 1),
+        };
+        notify(&config.notify, &event).await;
+        exit(code);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        let from = matches.value_of("from").unwrap_or("");
+        let to = matches.value_of("to").unwrap_or("");
+        let amount: f64 = parse_arg(matches, "amount").unwrap_or(0.0);
+        if let Some(r) = convert(from, to, &amount).await {
+            ledger::record(ledger::conversion_entry(&r));
+            println!("{:#?}", r);
+            logging::debug(&format!("{:#?}", r), fields());
+            exit(0);
+        }
+        exit(1);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("withdraw") {
+        let currency = matches.value_of("currency").unwrap_or("");
+        let amount: f64 = parse_arg(matches, "amount").unwrap_or(0.0);
+        let name = matches.value_of("to").unwrap_or("");
+        let config = load_config();
+        let destination = match config.addresses.get(name) {
+            Some(d) => d,
+            None => fail(
+                &format!(
+                    "No address named '{}' in the address book at {}",
+                    name,
+                    config::config_path().display()
+                ),
+                ErrorKind::Config,
+            ),
         };
-        println!("Depositing ${} USD into Coinbase...", amount);
-        if let Some(r) = make_deposit(&amount).await {
-            println!("Successfully deposited ${} into Coinbase!", amount);
+        if let Some(r) = withdraw(
+            currency,
+            amount,
+            name,
+            destination,
+            matches.is_present("yes"),
+        )
+        .await
+        {
             println!("{:#?}", r);
-            std::process::exit(0);
+            logging::debug(&format!("{:#?}", r), fields().product(currency));
+            exit(0);
+        }
+        exit(1);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("sweep") {
+        let config = load_config();
+        let dry_run = matches.is_present("dry-run");
+        let ok = sweep(&config, dry_run).await;
+        if !dry_run {
+            let event = if ok {
+                Event::new(EventKind::Success, "sweep", "sweep complete")
+            } else {
+                Event::new(EventKind::Failure, "sweep", "sweep failed")
+            };
+            notify(&config.notify, &event).await;
+        }
+        exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("ledger") {
+        let filter = ledger::Filter {
+            since: matches.value_of("since").map(String::from),
+            until: matches.value_of("until").map(String::from),
+            product: matches.value_of("product").map(String::from),
+            kind: parse_arg(matches, "type"),
+        };
+        let result = ledger::open()
+            .and_then(|conn| ledger::query(&conn, &filter))
+            .and_then(|entries| ledger::print(&entries, matches.value_of("format").unwrap_or("")));
+        if let Err(e) = result {
+            fail(&e, ErrorKind::Storage);
+        }
+        exit(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("reconcile") {
+        let options = reconcile::ReconcileOptions {
+            since: matches.value_of("since").map(String::from),
+            until: matches.value_of("until").map(String::from),
+            products: matches
+                .values_of("product")
+                .map(|v| v.map(String::from).collect())
+                .unwrap_or_default(),
+            import: matches.is_present("import"),
+            update: matches.is_present("update"),
+        };
+        match reconcile::reconcile(&options).await {
+            Ok(true) => exit(0),
+            Ok(false) => exit(1),
+            Err(e) => fail(&e, ErrorKind::Storage),
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("daemon") {
+        if let Err(e) = daemon::run(load_config()).await {
+            fail(&e, ErrorKind::Config);
+        }
+        exit(0);
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("catch-up") {
+        match daemon::catch_up(load_config()).await {
+            Ok(true) => exit(0),
+            Ok(false) => exit(1),
+            Err(e) => fail(&e, ErrorKind::Config),
         }
-        std::process::exit(1);
     }
 
     if let Some(matches) = matches.subcommand_matches("buy") {
         let currency = match matches.value_of("currency") {
             Some(s) => s,
-            None => {
-                println!("You must enter a currency to purchase");
-                std::process::exit(1);
-            }
+            None => fail(
+                "You must enter a currency to purchase",
+                ErrorKind::InvalidRequest,
+            ),
         };
         let amount = match matches.value_of("amount") {
             Some(s) => match s.parse::<f64>() {
                 Ok(a) => a,
-                _ => {
-                    println!("'{}' is an invalid dollar amount", s);
-                    std::process::exit(1);
-                }
+                _ => fail(
+                    &format!("'{}' is an invalid dollar amount", s),
+                    ErrorKind::InvalidRequest,
+                ),
             },
-            None => {
-                println!("You must enter an amount to deposit");
-                std::process::exit(1);
+            None => fail(
+                "You must enter an amount to deposit",
+                ErrorKind::InvalidRequest,
+            ),
+        };
+        let strategy = if matches.value_of("strategy") == Some("ma") {
+            Some(MovingAverageOptions {
+                days: parse_arg(matches, "ma-days").unwrap_or(0),
+                bands: match parse_bands(matches.value_of("bands").unwrap_or("")) {
+                    Ok(b) => b,
+                    Err(e) => fail(&format!("Invalid bands: {}", e), ErrorKind::InvalidRequest),
+                },
+                min_amount: parse_arg(matches, "min-amount"),
+                max_amount: parse_arg(matches, "max-amount"),
+            })
+        } else {
+            None
+        };
+        let execution = if matches.value_of("execution") == Some("maker") {
+            Some(MakerOptions {
+                attempts: parse_arg(matches, "attempts").unwrap_or(0),
+                reprice_secs: parse_arg(matches, "reprice-secs").unwrap_or(0),
+                deadline_secs: parse_arg(matches, "deadline-secs").unwrap_or(0),
+                fallback: match matches.value_of("fallback") {
+                    Some("none") => Fallback::GiveUp,
+                    _ => Fallback::Market,
+                },
+            })
+        } else {
+            None
+        };
+        let config = load_config();
+        let options = BuyOptions {
+            currency: String::from(currency),
+            amount,
+            strategy,
+            execution,
+            on_insufficient_funds: parse_arg(matches, "on-insufficient-funds")
+                .unwrap_or(config.buy.on_insufficient_funds),
+        };
+        let job = format!("buy {} {}", currency, amount);
+        let (event, code) = match buy(&options, &config).await {
+            BuyOutcome::Bought(orders) => (
+                Event {
+                    summary: Some(summarize_orders(&orders)),
+                    ..Event::new(EventKind::Success, &job, "purchase successful")
+                },
+                0,
+            ),
+            BuyOutcome::Queued => (
+                Event::new(EventKind::Success, &job, "queued for the next run"),
+                0,
+            ),
+            BuyOutcome::Skipped => (
+                Event::new(EventKind::Failure, &job, "skipped for insufficient funds"),
+                EXIT_INSUFFICIENT_FUNDS,
+            ),
+            BuyOutcome::Refused(e) => (Event::new(EventKind::Refusal, &job, &e), 1),
+            BuyOutcome::Failed => {
+                market::suggest_products(&format!("{}-USD", currency)).await;
+                (Event::new(EventKind::Failure, &job, "buy failed"), 1)
             }
         };
-        println!("Purchasing ${} worth of {}...", amount, currency);
-        match place_order(&amount, currency).await {
-            Some(r) => {
-                println!("Purchase successful!");
+        notify(&config.notify, &event).await;
+        check_balances(&config.notify, &job).await;
+        exit(code);
+    }
+
+    for (name, kind) in &[("stop-sell", StopKind::Loss), ("stop-buy", StopKind::Entry)] {
+        if let Some(matches) = matches.subcommand_matches(name) {
+            let currency = matches.value_of("currency").unwrap_or("");
+            let stop_price = matches.value_of("stop-price").unwrap_or("");
+            if let Some(r) = place_stop(
+                *kind,
+                currency,
+                matches.value_of("size"),
+                matches.value_of("funds"),
+                stop_price,
+                matches.value_of("limit-price"),
+            )
+            .await
+            {
                 println!("{:#?}", r);
-                std::process::exit(0);
-            }
-            None => {
-                std::process::exit(1);
+                logging::debug(&format!("{:#?}", r), fields());
+                exit(0);
             }
-        };
+            market::suggest_products(&format!("{}-USD", currency)).await;
+            exit(1);
+        }
     }
 
     if let Some(matches) = matches.subcommand_matches("report") {
@@ -242,11 +1249,122 @@ async fn main() {
         let end = matches.value_of("end").unwrap_or(&default_end[..]);
         if let Some(r) = request_report(start, end, account, email).await {
             println!("{:?}", r);
-            std::process::exit(0);
+            exit(0);
+        };
+        exit(1);
+    }
+
+    fail(
+        "Invalid input. Type help for more information",
+        ErrorKind::InvalidRequest,
+    );
+}
+
+/// Apply the guardrails shared by deposits and fiat withdrawals and resolve the bank
+/// account to use, exiting on a refusal or after reporting a --dry-run
+async fn check_fiat_transfer(
+    matches: &clap::ArgMatches<'_>,
+    amount: f64,
+    config: &config::Config,
+    action: &str,
+) -> String {
+    let withdrawing = action == "withdraw";
+    let max = if withdrawing {
+        config.guardrails.max_fiat_withdrawal
+    } else {
+        config.guardrails.max_deposit
+    };
+    if let Err(e) = fiat::check_amount(amount, max, action) {
+        let command = if withdrawing {
+            "withdraw-fiat"
+        } else {
+            "deposit"
+        };
+        let job = format!("{} {}", command, amount);
+        notify(&config.notify, &Event::new(EventKind::Refusal, &job, &e)).await;
+        fail(&e, ErrorKind::Refused);
+    }
+    let bank_id =
+        match fiat::payment_method_id(matches.value_of("payment-method"), withdrawing).await {
+            Ok(id) => id,
+            Err(e) => fail(&e, ErrorKind::Config),
         };
-        std::process::exit(1);
+    if matches.is_present("dry-run") {
+        println!(
+            "Dry run: would {} ${} USD using payment method {}",
+            action, amount, bank_id
+        );
+        exit(0);
     }
+    bank_id
+}
 
-    eprintln!("Invalid input. Type help for more information");
-    std::process::exit(1);
+/// Set up logging from the `[log]` section of the config and the `--log-*` options
+fn init_logging(matches: &clap::ArgMatches, command: &str) {
+    // A broken config is reported by the commands that need it
+    let mut settings = config::load().map(|c| c.log).unwrap_or_default();
+    // Global options are only propagated down, so look where they were given
+    let value_of = |name: &str| {
+        matches
+            .subcommand()
+            .1
+            .and_then(|m| m.value_of(name))
+            .or_else(|| matches.value_of(name))
+            .map(String::from)
+    };
+    if let Some(level) = value_of("log-level") {
+        settings.level = level.parse().ok();
+    }
+    if let Some(format) = value_of("log-format") {
+        settings.format = format.parse().ok();
+    }
+    if let Some(file) = value_of("log-file") {
+        settings.file = Some(file.into());
+    }
+    logging::init(&settings, command);
+}
+
+/// Load the config file, exiting if it exists but can't be read
+fn load_config() -> config::Config {
+    match config::load() {
+        Ok(c) => c,
+        Err(e) => fail(&e, ErrorKind::Config),
+    }
+}
+
+/// Parse an optional argument, exiting if it was provided but is not valid
+fn parse_arg<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str) -> Option<T> {
+    matches.value_of(name).map(|s| match s.parse::<T>() {
+        Ok(v) => v,
+        _ => fail(
+            &format!("'{}' is an invalid value for {}", s, name),
+            ErrorKind::InvalidRequest,
+        ),
+    })
+}
+
+/// Log `message` as an error of `kind` and exit
+fn fail(message: &str, kind: ErrorKind) -> ! {
+    logging::error(message, fields().error(kind));
+    exit(1)
+}
+
+/// Exit with `code`, first recording the run in the metrics textfile if one is
+/// configured, then releasing the profile's lock. The daemon serves its metrics over
+/// HTTP instead.
+fn exit(code: i32) -> ! {
+    let command = COMMAND.get().map(String::as_str).unwrap_or("");
+    if !command.is_empty() && command != "daemon" {
+        if let Ok(config) = config::load() {
+            if let Some(path) = &config.metrics.textfile {
+                metrics::record_run(command, code == 0);
+                if let Err(e) = metrics::write_textfile(path) {
+                    logging::warn(&e, fields().error(ErrorKind::Storage));
+                }
+            }
+        }
+    }
+    // The textfile is shared with other runs, so it's written while the lock is held
+    lock::release();
+    std::process::exit(code)
 }
@@ -0,0 +1,206 @@
+use crate::api::{list_products, Currency, Product, Stats, Trade};
+use crate::logging::{self, fields, ErrorKind};
+use std::io;
+
+// Suggest at most this many product ids for a mistyped one
+static MAX_SUGGESTIONS: usize = 3;
+
+fn parse(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
+/// Print trades as CSV or as a JSON array
+pub fn print_trades(trades: &[Trade], format: &str) -> Result<(), String> {
+    match format {
+        "json" => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(trades).expect("Trades are always serializable")
+            );
+            Ok(())
+        }
+        _ => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for trade in trades {
+                writer
+                    .serialize(trade)
+                    .map_err(|e| format!("Failed to write CSV: {}", e))?;
+            }
+            writer
+                .flush()
+                .map_err(|e| format!("Failed to write CSV: {}", e))
+        }
+    }
+}
+
+/// The 24 hour stats of a product, with the change since the open
+pub fn format_stats(product_id: &str, stats: &Stats) -> String {
+    let open = parse(&stats.open);
+    let change = if open > 0.0 {
+        format!(" ({:+.2}%)", (parse(&stats.last) - open) / open * 100.0)
+    } else {
+        String::new()
+    };
+    [
+        format!("{} over the last 24 hours", product_id),
+        format!("Open:          {}", stats.open),
+        format!("High:          {}", stats.high),
+        format!("Low:           {}", stats.low),
+        format!("Last:          {}{}", stats.last, change),
+        format!("Volume:        {}", stats.volume),
+        format!("30-day volume: {}", stats.volume_30day),
+    ]
+    .join("\n")
+}
+
+/// Products quoted in `quote` (if given) with the given `status`, sorted by id
+pub fn filter_products(
+    mut products: Vec<Product>,
+    quote: Option<&str>,
+    status: Option<&str>,
+) -> Vec<Product> {
+    products.retain(|p| {
+        quote.is_none_or(|q| p.quote_currency.eq_ignore_ascii_case(q))
+            && status.is_none_or(|s| p.status == s)
+    });
+    products.sort_by(|a, b| a.id.cmp(&b.id));
+    products
+}
+
+/// Whether a product can be traded, and with which restrictions
+fn trading_status(product: &Product) -> String {
+    if product.trading_disabled {
+        return String::from("trading disabled");
+    }
+    let restrictions: Vec<&str> = [
+        (product.cancel_only, "cancel only"),
+        (product.limit_only, "limit only"),
+        (product.post_only, "post only"),
+    ]
+    .iter()
+    .filter(|(on, _)| *on)
+    .map(|(_, name)| *name)
+    .collect();
+    if restrictions.is_empty() {
+        product.status.clone()
+    } else {
+        format!("{} ({})", product.status, restrictions.join(", "))
+    }
+}
+
+pub fn format_products(products: &[Product]) -> String {
+    let mut out = format!(
+        "{:<12} {:>12} {:>12} {:>12} {:>10}  {}\n",
+        "product", "base incr", "quote incr", "min size", "min funds", "status"
+    );
+    for p in products {
+        out.push_str(&format!(
+            "{:<12} {:>12} {:>12} {:>12} {:>10}  {}\n",
+            p.id,
+            p.base_increment,
+            p.quote_increment,
+            p.base_min_size.as_deref().unwrap_or("-"),
+            p.min_market_funds.as_deref().unwrap_or("-"),
+            trading_status(p)
+        ));
+    }
+    out
+}
+
+pub fn format_currencies(currencies: &[Currency]) -> String {
+    let mut out = format!(
+        "{:<8} {:<24} {:>12} {:>12}  {}\n",
+        "currency", "name", "min size", "precision", "status"
+    );
+    for c in currencies {
+        out.push_str(&format!(
+            "{:<8} {:<24} {:>12} {:>12}  {}\n",
+            c.id,
+            c.name,
+            c.min_size,
+            c.max_precision.as_deref().unwrap_or("-"),
+            c.status
+        ));
+    }
+    out
+}
+
+/// The number of single character insertions, deletions and substitutions between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The ids in `ids` that `product_id` was most likely a typo of, closest first. Case is
+/// ignored, and so are the halves of the pair being the wrong way around (USD-BTC)
+pub fn close_matches<'a>(product_id: &str, ids: &'a [String]) -> Vec<&'a str> {
+    let typed = product_id.to_uppercase();
+    let swapped = match typed.split_once('-') {
+        Some((base, quote)) => format!("{}-{}", quote, base),
+        None => typed.clone(),
+    };
+    // Allow about one typo in four characters, and at least two
+    let allowed = (typed.len() / 4).max(2);
+    let mut matches: Vec<(usize, &str)> = ids
+        .iter()
+        .map(|id| {
+            let distance = edit_distance(&typed, id).min(edit_distance(&swapped, id));
+            (distance, id.as_str())
+        })
+        .filter(|(distance, _)| *distance <= allowed)
+        .collect();
+    matches.sort();
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, id)| id)
+        .collect()
+}
+
+/// What to tell someone who asked for `product_id`, if it isn't one of `ids`
+pub fn product_suggestion(product_id: &str, ids: &[String]) -> Option<String> {
+    if ids.iter().any(|id| id == product_id) {
+        return None;
+    }
+    let suggestions = close_matches(product_id, ids);
+    Some(if suggestions.is_empty() {
+        format!(
+            "There is no product {}; see `hodl products` for the list",
+            product_id
+        )
+    } else {
+        format!(
+            "There is no product {}; did you mean {}?",
+            product_id,
+            suggestions.join(" or ")
+        )
+    })
+}
+
+/// If `product_id` isn't listed on the exchange, say so and suggest what might have been meant
+pub async fn suggest_products(product_id: &str) {
+    let ids: Vec<String> = match list_products().await {
+        Some(p) => p.into_iter().map(|p| p.id).collect(),
+        None => return,
+    };
+    if let Some(message) = product_suggestion(product_id, &ids) {
+        logging::error(
+            &message,
+            fields()
+                .product(product_id)
+                .error(ErrorKind::InvalidRequest),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests;
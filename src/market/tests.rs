@@ -0,0 +1,93 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn test_format_stats() {
+    let stats: Stats = serde_json::from_value(json!({
+        "open": "100.00",
+        "high": "110.00",
+        "low": "95.00",
+        "last": "105.00",
+        "volume": "1234.5",
+        "volume_30day": "40000.1",
+    }))
+    .unwrap();
+    let text = format_stats("BTC-USD", &stats);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "BTC-USD over the last 24 hours");
+    assert_eq!(lines[4], "Last:          105.00 (+5.00%)");
+    assert_eq!(lines[6], "30-day volume: 40000.1");
+
+    let unopened = Stats {
+        open: String::from("0"),
+        ..stats
+    };
+    assert!(format_stats("BTC-USD", &unopened).contains("Last:          105.00\n"));
+}
+
+fn product(id: &str, status: &str) -> Product {
+    let (base, quote) = id.split_once('-').unwrap();
+    serde_json::from_value(json!({
+        "id": id,
+        "base_currency": base,
+        "quote_currency": quote,
+        "base_increment": "0.00000001",
+        "quote_increment": "0.01",
+        "base_min_size": "0.001",
+        "min_market_funds": "5",
+        "status": status,
+        "post_only": id == "ETH-BTC",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_filter_products() {
+    let products = vec![
+        product("ETH-USD", "online"),
+        product("BTC-USD", "online"),
+        product("ETH-BTC", "online"),
+        product("XYZ-USD", "delisted"),
+    ];
+    let ids = |products: &[Product]| products.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+    let usd = filter_products(products, Some("usd"), Some("online"));
+    assert_eq!(ids(&usd), vec!["BTC-USD", "ETH-USD"]);
+
+    let all = filter_products(vec![product("ETH-BTC", "online")], None, None);
+    assert!(format_products(&all).contains("online (post only)"));
+}
+
+#[test]
+fn test_close_matches() {
+    let ids: Vec<String> = ["BTC-USD", "BTC-EUR", "ETH-USD", "ETC-USD", "LTC-USD"]
+        .iter()
+        .map(|s| String::from(*s))
+        .collect();
+    assert_eq!(close_matches("btc-usd", &ids)[0], "BTC-USD");
+    assert_eq!(close_matches("BTC-UDS", &ids)[0], "BTC-USD");
+    assert_eq!(close_matches("USD-BTC", &ids)[0], "BTC-USD");
+    assert_eq!(
+        close_matches("ETX-USD", &ids),
+        vec!["ETC-USD", "ETH-USD", "BTC-USD"]
+    );
+    assert!(close_matches("DOGE-GBP", &ids).is_empty());
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+}
+
+#[test]
+fn test_product_suggestion() {
+    let ids: Vec<String> = ["BTC-USD", "ETH-USD"]
+        .iter()
+        .map(|s| String::from(*s))
+        .collect();
+    // As when `hodl history BTC-UDS ...` fails
+    assert_eq!(
+        product_suggestion("BTC-UDS", &ids).unwrap(),
+        "There is no product BTC-UDS; did you mean BTC-USD?"
+    );
+    assert!(product_suggestion("DOGE-GBP", &ids)
+        .unwrap()
+        .contains("see `hodl products`"));
+    // A failure for a product that exists isn't the product id's fault
+    assert_eq!(product_suggestion("BTC-USD", &ids), None);
+}
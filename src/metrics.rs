@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+pub static ORDERS_PLACED: &str = "hodl_orders_placed_total";
+pub static ORDERS_FAILED: &str = "hodl_orders_failed_total";
+pub static USD_SPENT: &str = "hodl_usd_spent_total";
+pub static JOB_LAST_RUN: &str = "hodl_job_last_run_timestamp_seconds";
+pub static JOB_LAST_SUCCESS: &str = "hodl_job_last_success_timestamp_seconds";
+pub static API_DURATION_SUM: &str = "hodl_api_request_duration_seconds_sum";
+pub static API_DURATION_COUNT: &str = "hodl_api_request_duration_seconds_count";
+pub static API_ERRORS: &str = "hodl_api_errors_total";
+pub static BALANCE: &str = "hodl_balance";
+pub static AVAILABLE: &str = "hodl_balance_available";
+
+/// Every metric hodl exports: name, type and help, in the order they're rendered.
+/// Summaries are exported as their `_sum` and `_count` series.
+static DEFINITIONS: &[(&str, &str, &str)] = &[
+    (
+        "hodl_orders_placed_total",
+        "counter",
+        "Orders accepted by the exchange",
+    ),
+    (
+        "hodl_orders_failed_total",
+        "counter",
+        "Orders the exchange rejected",
+    ),
+    (
+        "hodl_usd_spent_total",
+        "counter",
+        "USD spent on filled buys, including fees",
+    ),
+    (
+        "hodl_job_last_run_timestamp_seconds",
+        "gauge",
+        "When a job or command last ran",
+    ),
+    (
+        "hodl_job_last_success_timestamp_seconds",
+        "gauge",
+        "When a job or command last succeeded",
+    ),
+    (
+        "hodl_api_request_duration_seconds",
+        "summary",
+        "Time taken by Coinbase API requests",
+    ),
+    (
+        "hodl_api_errors_total",
+        "counter",
+        "Coinbase API requests that failed or returned an error status",
+    ),
+    ("hodl_balance", "gauge", "Balance by currency"),
+    (
+        "hodl_balance_available",
+        "gauge",
+        "Balance available to trade or withdraw, by currency",
+    ),
+];
+
+/// Metric values by name, then by rendered labels (ex: `product="BTC-USD"`)
+#[derive(Debug, Default, PartialEq)]
+pub struct Metrics {
+    series: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    series: BTreeMap::new(),
+});
+
+fn definition(name: &str) -> Option<&'static (&'static str, &'static str, &'static str)> {
+    let base = name
+        .strip_suffix("_sum")
+        .or_else(|| name.strip_suffix("_count"))
+        .filter(|b| DEFINITIONS.iter().any(|d| d.0 == *b && d.1 == "summary"))
+        .unwrap_or(name);
+    DEFINITIONS.iter().find(|d| d.0 == base)
+}
+
+fn is_cumulative(name: &str) -> bool {
+    definition(name).is_some_and(|d| d.1 != "gauge")
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(k, v)| {
+            let v = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", k, v)
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl Metrics {
+    pub fn inc(&mut self, name: &str, labels: &[(&str, &str)], by: f64) {
+        *self
+            .series
+            .entry(String::from(name))
+            .or_default()
+            .entry(format_labels(labels))
+            .or_insert(0.0) += by;
+    }
+
+    pub fn set(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.series
+            .entry(String::from(name))
+            .or_default()
+            .insert(format_labels(labels), value);
+    }
+
+    /// In the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (base, kind, help) in DEFINITIONS {
+            let names: Vec<String> = if *kind == "summary" {
+                vec![format!("{}_sum", base), format!("{}_count", base)]
+            } else {
+                vec![String::from(*base)]
+            };
+            if !names.iter().any(|n| self.series.contains_key(n)) {
+                continue;
+            }
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n",
+                base, help, base, kind
+            ));
+            for name in &names {
+                for (labels, value) in self.series.get(name).into_iter().flatten() {
+                    if labels.is_empty() {
+                        out.push_str(&format!("{} {}\n", name, value));
+                    } else {
+                        out.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Fold in the samples of an earlier `render`: counters add up across runs, and
+    /// gauges keep their old value unless this run set them
+    pub fn merge(&mut self, previous: &str) {
+        for line in previous.lines().filter(|l| !l.starts_with('#')) {
+            let (series, value) = match line.rsplit_once(' ') {
+                Some((s, v)) => match v.parse::<f64>() {
+                    Ok(v) => (s, v),
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            let (name, labels) = match series.split_once('{') {
+                Some((n, l)) => (n, l.trim_end_matches('}')),
+                None => (series, ""),
+            };
+            if definition(name).is_none() {
+                continue;
+            }
+            let values = self.series.entry(String::from(name)).or_default();
+            if is_cumulative(name) {
+                *values.entry(String::from(labels)).or_insert(0.0) += value;
+            } else {
+                values.entry(String::from(labels)).or_insert(value);
+            }
+        }
+    }
+}
+
+fn with_metrics<T>(f: impl FnOnce(&mut Metrics) -> T) -> T {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut metrics)
+}
+
+pub fn inc(name: &str, labels: &[(&str, &str)], by: f64) {
+    with_metrics(|m| m.inc(name, labels, by))
+}
+
+pub fn set(name: &str, labels: &[(&str, &str)], value: f64) {
+    with_metrics(|m| m.set(name, labels, value))
+}
+
+/// Record how long an API request took and whether it failed
+pub fn observe_request(method: &str, seconds: f64, ok: bool) {
+    with_metrics(|m| {
+        m.inc(API_DURATION_SUM, &[("method", method)], seconds);
+        m.inc(API_DURATION_COUNT, &[("method", method)], 1.0);
+        if !ok {
+            m.inc(API_ERRORS, &[("method", method)], 1.0);
+        }
+    })
+}
+
+/// Record that `job` ran just now, and whether it succeeded
+pub fn record_run(job: &str, success: bool) {
+    let now = chrono::Utc::now().timestamp() as f64;
+    with_metrics(|m| {
+        m.set(JOB_LAST_RUN, &[("job", job)], now);
+        if success {
+            m.set(JOB_LAST_SUCCESS, &[("job", job)], now);
+        }
+    })
+}
+
+pub fn render() -> String {
+    with_metrics(|m| m.render())
+}
+
+/// Merge this run's metrics into a node_exporter textfile, replacing it atomically
+/// so the exporter never reads half a file
+pub fn write_textfile(path: &Path) -> Result<(), String> {
+    let contents = with_metrics(|m| {
+        if let Ok(previous) = fs::read_to_string(path) {
+            m.merge(&previous);
+        }
+        m.render()
+    });
+    let tmp = path.with_extension("prom.tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Serve `GET /metrics` on `addr` until the daemon exits, returning the address
+/// actually bound (`addr` may use port 0)
+pub async fn serve(addr: &str) -> Result<SocketAddr, String> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    let bound = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let response = if request.starts_with("GET /metrics ") {
+                    let body = render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                };
+                // A scraper that hung up early will try again
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests;
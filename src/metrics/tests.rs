@@ -0,0 +1,71 @@
+use super::*;
+
+#[test]
+fn test_render() {
+    let mut metrics = Metrics::default();
+    metrics.inc(ORDERS_PLACED, &[("product", "BTC-USD")], 1.0);
+    metrics.inc(ORDERS_PLACED, &[("product", "BTC-USD")], 1.0);
+    metrics.inc(API_DURATION_SUM, &[("method", "GET")], 0.25);
+    metrics.inc(API_DURATION_COUNT, &[("method", "GET")], 1.0);
+    metrics.set(JOB_LAST_SUCCESS, &[("job", "say \"hi\"")], 1610237220.0);
+    assert_eq!(
+        metrics.render(),
+        "# HELP hodl_orders_placed_total Orders accepted by the exchange
+# TYPE hodl_orders_placed_total counter
+hodl_orders_placed_total{product=\"BTC-USD\"} 2
+# HELP hodl_job_last_success_timestamp_seconds When a job or command last succeeded
+# TYPE hodl_job_last_success_timestamp_seconds gauge
+hodl_job_last_success_timestamp_seconds{job=\"say \\\"hi\\\"\"} 1610237220
+# HELP hodl_api_request_duration_seconds Time taken by Coinbase API requests
+# TYPE hodl_api_request_duration_seconds summary
+hodl_api_request_duration_seconds_sum{method=\"GET\"} 0.25
+hodl_api_request_duration_seconds_count{method=\"GET\"} 1
+"
+    );
+}
+
+#[test]
+fn test_merge() {
+    let mut previous = Metrics::default();
+    previous.inc(USD_SPENT, &[("product", "BTC-USD")], 10.0);
+    previous.inc(API_DURATION_COUNT, &[("method", "GET")], 3.0);
+    previous.set(JOB_LAST_SUCCESS, &[("job", "buy")], 100.0);
+    previous.set(JOB_LAST_SUCCESS, &[("job", "deposit")], 50.0);
+
+    let mut current = Metrics::default();
+    current.inc(USD_SPENT, &[("product", "BTC-USD")], 8.0);
+    current.inc(API_DURATION_COUNT, &[("method", "GET")], 1.0);
+    current.set(JOB_LAST_SUCCESS, &[("job", "buy")], 200.0);
+    current.merge(&previous.render());
+
+    let mut expected = Metrics::default();
+    expected.inc(USD_SPENT, &[("product", "BTC-USD")], 18.0);
+    expected.inc(API_DURATION_COUNT, &[("method", "GET")], 4.0);
+    expected.set(JOB_LAST_SUCCESS, &[("job", "buy")], 200.0);
+    expected.set(JOB_LAST_SUCCESS, &[("job", "deposit")], 50.0);
+    assert_eq!(current, expected);
+}
+
+#[tokio::test]
+async fn test_serve() {
+    use tokio::net::TcpStream;
+
+    set(BALANCE, &[("currency", "USD")], 42.0);
+    let addr = serve("127.0.0.1:0").await.unwrap();
+
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    socket
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("hodl_balance{currency=\"USD\"} 42\n"));
+
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    socket.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"));
+}
@@ -0,0 +1,376 @@
+use crate::api::{get_accounts, Order};
+use crate::logging::{self, fields, ErrorKind};
+use chrono::Local;
+use openssl::ssl::{SslConnector, SslMethod};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+static DEFAULT_TEMPLATE: &str = "hodl {event} for {job}: {message}\n{summary}";
+static DEFAULT_SUBJECT: &str = "hodl {event}: {job}";
+static TIMEOUT_SECS: u64 = 10;
+
+/// What happened to a job (or a command run from cron)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    Success,
+    Failure,
+    /// A guardrail refused to move the money
+    Refusal,
+    /// An available balance fell below its `low_balance` threshold
+    LowBalance,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Success => "success",
+            EventKind::Failure => "failure",
+            EventKind::Refusal => "refusal",
+            EventKind::LowBalance => "low-balance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub job: String,
+    pub message: String,
+    /// Extra detail, such as what the orders filled at
+    pub summary: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, job: &str, message: &str) -> Event {
+        Event {
+            kind,
+            job: String::from(job),
+            message: String::from(message),
+            summary: None,
+        }
+    }
+}
+
+/// The `[notify]` section of the config
+#[derive(Debug, Default, Deserialize)]
+pub struct NotifySettings {
+    /// Minimum available balances by currency (ex: USD = 25.0)
+    #[serde(default)]
+    pub low_balance: HashMap<String, f64>,
+    #[serde(default)]
+    pub webhook: Vec<Webhook>,
+    #[serde(default)]
+    pub email: Vec<Email>,
+}
+
+/// POSTs each event as JSON to `url`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Only notify of these events; defaults to all of them
+    pub events: Option<Vec<EventKind>>,
+    /// Template for the payload's `text` field
+    pub template: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    /// Plain text; only for a relay on localhost
+    None,
+    /// Upgrade the connection with STARTTLS, usually on port 587
+    StartTls,
+    /// Connect with TLS from the start, usually on port 465
+    Implicit,
+}
+
+/// Sends each event as an email through an SMTP server
+#[derive(Debug, Clone, Deserialize)]
+pub struct Email {
+    pub host: String,
+    pub port: u16,
+    pub tls: Tls,
+    pub username: Option<String>,
+    /// The environment variable holding the SMTP password, to keep it out of the config
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub events: Option<Vec<EventKind>>,
+    pub subject: Option<String>,
+    pub template: Option<String>,
+}
+
+fn wants(events: &Option<Vec<EventKind>>, kind: EventKind) -> bool {
+    events.as_ref().is_none_or(|e| e.contains(&kind))
+}
+
+/// Fill in `{event}`, `{job}`, `{message}`, `{summary}` and `{time}`
+pub fn render(template: &str, event: &Event, time: &str) -> String {
+    template
+        .replace("{event}", event.kind.as_str())
+        .replace("{job}", &event.job)
+        .replace("{message}", &event.message)
+        .replace("{summary}", event.summary.as_deref().unwrap_or(""))
+        .replace("{time}", time)
+        .trim_end()
+        .to_string()
+}
+
+/// What a set of orders came to: USD spent, size filled and fees, per product
+pub fn summarize_orders(orders: &[Order]) -> String {
+    let parse = |v: &str| v.parse::<f64>().unwrap_or(0.0);
+    orders
+        .iter()
+        .map(|o| {
+            let value = parse(&o.executed_value);
+            let size = parse(&o.filled_size);
+            let price = if size > 0.0 { value / size } else { 0.0 };
+            format!(
+                "{} {} {}: ${:.2} for {} at ~{:.2}, ${:.4} in fees ({})",
+                o.side,
+                o.product_id,
+                o.id,
+                value,
+                o.filled_size,
+                price,
+                parse(&o.fill_fees),
+                o.status
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub async fn send_webhook(hook: &Webhook, event: &Event) -> Result<(), String> {
+    let time = Local::now().to_rfc3339();
+    let payload = serde_json::json!({
+        "event": event.kind.as_str(),
+        "job": event.job,
+        "message": event.message,
+        "summary": event.summary,
+        "time": time,
+        "text": render(hook.template.as_deref().unwrap_or(DEFAULT_TEMPLATE), event, &time),
+    });
+    let client = Client::builder()
+        .user_agent("hodl")
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .build()
+        .map_err(|e| format!("Failed to create an HTTP client: {}", e))?;
+    let response = client
+        .post(&hook.url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Webhook {} failed: {}", hook.url, e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Webhook {} responded with {}",
+            hook.url,
+            response.status()
+        ));
+    }
+    Ok(())
+}
+
+/// Read one (possibly multi-line) SMTP reply, failing unless its code is `expected`
+fn expect_reply<S: Read>(stream: &mut S, expected: u16) -> Result<String, String> {
+    let mut reply = String::new();
+    loop {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while !line.ends_with(b"\r\n") {
+            match stream.read(&mut byte) {
+                Ok(0) => return Err(String::from("SMTP server closed the connection")),
+                Ok(_) => line.push(byte[0]),
+                Err(e) => return Err(format!("Failed to read from the SMTP server: {}", e)),
+            }
+        }
+        let line = String::from_utf8_lossy(&line).into_owned();
+        reply.push_str(&line);
+        // "250-..." continues a reply, "250 ..." ends it
+        if line.len() < 4 || line.as_bytes()[3] != b'-' {
+            break;
+        }
+    }
+    match reply.get(..3).and_then(|c| c.parse::<u16>().ok()) {
+        Some(code) if code == expected => Ok(reply),
+        _ => Err(format!(
+            "Unexpected reply from the SMTP server: {}",
+            reply.trim_end()
+        )),
+    }
+}
+
+fn command<S: Read + Write>(stream: &mut S, line: &str, expected: u16) -> Result<String, String> {
+    stream
+        .write_all(format!("{}\r\n", line).as_bytes())
+        .map_err(|e| format!("Failed to write to the SMTP server: {}", e))?;
+    expect_reply(stream, expected)
+}
+
+/// The message, with lines starting with "." escaped so they don't end the DATA
+pub fn format_message(email: &Email, subject: &str, body: &str, date: &str) -> String {
+    let body = body
+        .lines()
+        .map(|l| {
+            if l.starts_with('.') {
+                format!(".{}", l)
+            } else {
+                String::from(l)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\r\n");
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.",
+        email.from,
+        email.to.join(", "),
+        subject,
+        date,
+        body
+    )
+}
+
+/// Everything after the greeting and EHLO: log in, send the message, and quit
+fn deliver<S: Read + Write>(stream: &mut S, email: &Email, message: &str) -> Result<(), String> {
+    if let Some(user) = &email.username {
+        let password = match &email.password_env {
+            Some(var) => env::var(var).map_err(|_| format!("Set {} to the SMTP password", var))?,
+            None => String::new(),
+        };
+        let credentials = base64::encode(format!("\0{}\0{}", user, password));
+        command(stream, &format!("AUTH PLAIN {}", credentials), 235)?;
+    }
+    command(stream, &format!("MAIL FROM:<{}>", email.from), 250)?;
+    for to in &email.to {
+        command(stream, &format!("RCPT TO:<{}>", to), 250)?;
+    }
+    command(stream, "DATA", 354)?;
+    command(stream, message, 250)?;
+    command(stream, "QUIT", 221)?;
+    Ok(())
+}
+
+/// Send one email. Blocking; SMTP is a short conversation over a single connection.
+pub fn send_email(email: &Email, event: &Event) -> Result<(), String> {
+    let now = Local::now();
+    let subject = render(
+        email.subject.as_deref().unwrap_or(DEFAULT_SUBJECT),
+        event,
+        &now.to_rfc3339(),
+    );
+    let body = render(
+        email.template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+        event,
+        &now.to_rfc3339(),
+    );
+    let message = format_message(email, &subject, &body, &now.to_rfc2822());
+
+    let tcp = TcpStream::connect((email.host.as_str(), email.port))
+        .map_err(|e| format!("Failed to connect to {}:{}: {}", email.host, email.port, e))?;
+    let timeout = Some(Duration::from_secs(TIMEOUT_SECS));
+    tcp.set_read_timeout(timeout)
+        .and_then(|_| tcp.set_write_timeout(timeout))
+        .map_err(|e| format!("Failed to set SMTP timeouts: {}", e))?;
+    let ehlo = "EHLO hodl";
+    let tls_connect = |tcp: TcpStream| {
+        SslConnector::builder(SslMethod::tls())
+            .map_err(|e| format!("Failed to set up TLS: {}", e))?
+            .build()
+            .connect(&email.host, tcp)
+            .map_err(|e| format!("TLS with {} failed: {}", email.host, e))
+    };
+    match email.tls {
+        Tls::None => {
+            let mut stream = tcp;
+            expect_reply(&mut stream, 220)?;
+            command(&mut stream, ehlo, 250)?;
+            deliver(&mut stream, email, &message)
+        }
+        Tls::Implicit => {
+            let mut stream = tls_connect(tcp)?;
+            expect_reply(&mut stream, 220)?;
+            command(&mut stream, ehlo, 250)?;
+            deliver(&mut stream, email, &message)
+        }
+        Tls::StartTls => {
+            let mut tcp = tcp;
+            expect_reply(&mut tcp, 220)?;
+            command(&mut tcp, ehlo, 250)?;
+            command(&mut tcp, "STARTTLS", 220)?;
+            let mut stream = tls_connect(tcp)?;
+            command(&mut stream, ehlo, 250)?;
+            deliver(&mut stream, email, &message)
+        }
+    }
+}
+
+/// Send `event` to every notifier that wants it. Failures are reported but don't
+/// affect the outcome of whatever triggered the event.
+pub async fn notify(settings: &NotifySettings, event: &Event) {
+    for hook in settings
+        .webhook
+        .iter()
+        .filter(|h| wants(&h.events, event.kind))
+    {
+        if let Err(e) = send_webhook(hook, event).await {
+            logging::warn(&e, fields().error(ErrorKind::Http));
+        }
+    }
+    for email in settings
+        .email
+        .iter()
+        .filter(|e| wants(&e.events, event.kind))
+    {
+        let (email, event) = (email.clone(), event.clone());
+        let result = tokio::task::spawn_blocking(move || send_email(&email, &event)).await;
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => logging::warn(&e, fields().error(ErrorKind::Http)),
+            Err(e) => logging::warn(
+                &format!("Sending email panicked: {}", e),
+                fields().error(ErrorKind::Unexpected),
+            ),
+        }
+    }
+}
+
+/// Notify of every balance below its `low_balance` threshold
+pub async fn check_balances(settings: &NotifySettings, job: &str) {
+    if settings.low_balance.is_empty() {
+        return;
+    }
+    let accounts = match get_accounts().await {
+        Some(a) => a,
+        None => return,
+    };
+    let mut low: Vec<(&String, f64, f64)> = settings
+        .low_balance
+        .iter()
+        .map(|(currency, min)| {
+            let available = accounts
+                .iter()
+                .find(|a| &a.currency == currency)
+                .and_then(|a| a.available.parse::<f64>().ok())
+                .unwrap_or(0.0);
+            (currency, available, *min)
+        })
+        .filter(|(_, available, min)| available < min)
+        .collect();
+    low.sort_by(|a, b| a.0.cmp(b.0));
+    for (currency, available, min) in low {
+        let message = format!(
+            "{} available is {}, below the {} threshold",
+            currency, available, min
+        );
+        notify(settings, &Event::new(EventKind::LowBalance, job, &message)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests;
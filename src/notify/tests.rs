@@ -0,0 +1,170 @@
+use super::*;
+use crate::config;
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+use std::thread;
+
+fn event() -> Event {
+    Event {
+        summary: Some(String::from(".5 BTC")),
+        ..Event::new(EventKind::Success, "daily-btc", "placed 1 order(s)")
+    }
+}
+
+#[test]
+fn test_parse_settings() {
+    let config = config::parse(
+        r#"
+[notify]
+low_balance = { USD = 25.0 }
+
+[[notify.webhook]]
+url = "http://localhost:8080/hodl"
+events = ["failure", "low-balance"]
+
+[[notify.email]]
+host = "smtp.example.com"
+port = 587
+tls = "starttls"
+username = "hodl"
+password_env = "HODL_SMTP_PASSWORD"
+from = "hodl@example.com"
+to = ["me@example.com"]
+"#,
+    )
+    .unwrap();
+    let notify = &config.notify;
+    assert_eq!(notify.low_balance["USD"], 25.0);
+    assert!(!wants(&notify.webhook[0].events, EventKind::Success));
+    assert!(wants(&notify.webhook[0].events, EventKind::LowBalance));
+    assert_eq!(notify.email[0].tls, Tls::StartTls);
+    assert!(wants(&notify.email[0].events, EventKind::Refusal));
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(
+        render(DEFAULT_TEMPLATE, &event(), "now"),
+        "hodl success for daily-btc: placed 1 order(s)\n.5 BTC"
+    );
+    let failed = Event::new(EventKind::Failure, "sweep", "sweep failed");
+    assert_eq!(
+        render("{time} {event} {job}: {message}{summary}", &failed, "now"),
+        "now failure sweep: sweep failed"
+    );
+}
+
+#[tokio::test]
+async fn test_send_webhook() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hook = Webhook {
+        url: format!("http://{}/hodl", listener.local_addr().unwrap()),
+        events: None,
+        template: Some(String::from("{job}: {message}")),
+    };
+    let server = tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        // Read until the whole JSON body has arrived
+        while !request.ends_with(b"}") {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    send_webhook(&hook, &event()).await.unwrap();
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /hodl HTTP/1.1"));
+    let body: serde_json::Value =
+        serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+    assert_eq!(body["event"], "success");
+    assert_eq!(body["job"], "daily-btc");
+    assert_eq!(body["summary"], ".5 BTC");
+    assert_eq!(body["text"], "daily-btc: placed 1 order(s)");
+}
+
+/// Play the part of an SMTP server for one message, returning what the client sent
+fn smtp_sink(listener: TcpListener) -> thread::JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut received = Vec::new();
+        stream.write_all(b"220 sink ESMTP\r\n").unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end_matches("\r\n").to_string();
+            received.push(line.clone());
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-sink\r\n250 AUTH PLAIN\r\n"
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                stream.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            stream.write_all(reply).unwrap();
+        }
+        received
+    })
+}
+
+#[test]
+fn test_send_email() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let email = Email {
+        host: String::from("127.0.0.1"),
+        port: listener.local_addr().unwrap().port(),
+        tls: Tls::None,
+        username: Some(String::from("hodl")),
+        password_env: Some(String::from("HODL_TEST_SMTP_PASSWORD")),
+        from: String::from("hodl@example.com"),
+        to: vec![
+            String::from("me@example.com"),
+            String::from("you@example.com"),
+        ],
+        events: None,
+        subject: None,
+        template: None,
+    };
+    env::set_var("HODL_TEST_SMTP_PASSWORD", "hunter2");
+    let sink = smtp_sink(listener);
+    send_email(&email, &event()).unwrap();
+
+    let received = sink.join().unwrap();
+    assert_eq!(received[0], "EHLO hodl");
+    assert_eq!(
+        received[1],
+        format!("AUTH PLAIN {}", base64::encode("\0hodl\0hunter2"))
+    );
+    assert_eq!(received[2], "MAIL FROM:<hodl@example.com>");
+    assert_eq!(received[3], "RCPT TO:<me@example.com>");
+    assert_eq!(received[4], "RCPT TO:<you@example.com>");
+    assert_eq!(received[5], "DATA");
+    assert!(received.contains(&String::from("Subject: hodl success: daily-btc")));
+    // A line starting with "." is escaped so it doesn't end the message early
+    assert!(received.contains(&String::from("..5 BTC")));
+    assert_eq!(received[received.len() - 2], ".");
+    assert_eq!(received[received.len() - 1], "QUIT");
+}
@@ -0,0 +1,74 @@
+use crate::config::{hodl_home, profile};
+use crate::lock;
+use crate::logging::{self, fields};
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+// Coinbase allows 5 private requests a second in bursts of up to 10; stay a little under
+static INTERVAL_MILLIS: i64 = 250;
+static BURST: i64 = 8;
+// The budget is only locked long enough to read and write a number
+static LOCK_WAIT_SECS: u64 = 10;
+static LOCK_POLL_MILLIS: u64 = 5;
+
+// Tasks in this process take turns at the budget file before other processes get a look in
+static TURN: OnceLock<Mutex<()>> = OnceLock::new();
+
+pub fn budget_path() -> PathBuf {
+    hodl_home().join(format!("{}.ratelimit", profile()))
+}
+
+/// The generic cell rate algorithm: `tat` is the theoretical arrival time of the
+/// next request, in milliseconds. Returns how long a request made at `now` must
+/// wait, and the `tat` to store for the next one
+pub fn schedule(tat: i64, now: i64, interval: i64, burst: i64) -> (i64, i64) {
+    let tat = tat.max(now);
+    let wait = (tat - interval * (burst - 1) - now).max(0);
+    (wait, tat + interval)
+}
+
+/// Claim the next slot in the budget at `path`, returning how long to wait for it
+pub async fn claim_at(path: &Path, now: i64) -> Result<i64, String> {
+    let _lock = lock::acquire_at(
+        &path.with_extension("ratelimit.lock"),
+        "rate limit",
+        Duration::from_secs(LOCK_WAIT_SECS),
+        Duration::from_millis(LOCK_POLL_MILLIS),
+    )
+    .await?;
+    // A missing or mangled budget just means nobody has made a request lately
+    let tat = fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .unwrap_or(0);
+    let (wait, tat) = schedule(tat, now, INTERVAL_MILLIS, BURST);
+    fs::write(path, tat.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(wait)
+}
+
+/// Wait for this profile's turn to make an exchange request, shared by every hodl
+/// process so that together they stay within the rate limit
+pub async fn wait_turn() {
+    let wait = {
+        let _turn = TURN.get_or_init(|| Mutex::new(())).lock().await;
+        match claim_at(&budget_path(), Utc::now().timestamp_millis()).await {
+            Ok(w) => w,
+            Err(e) => {
+                logging::warn(&format!("Ignoring the shared rate limit: {}", e), fields());
+                0
+            }
+        }
+    };
+    if wait > 0 {
+        sleep(Duration::from_millis(wait as u64)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests;
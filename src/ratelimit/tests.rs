@@ -0,0 +1,38 @@
+use super::*;
+use tempfile::tempdir;
+
+#[test]
+fn test_schedule() {
+    // A burst of 3 goes straight through, then requests are spaced by the interval
+    let mut tat = 0;
+    let mut waits = Vec::new();
+    for _ in 0..5 {
+        let (wait, next) = schedule(tat, 1000, 100, 3);
+        waits.push(wait);
+        tat = next;
+    }
+    assert_eq!(waits, vec![0, 0, 0, 100, 200]);
+    assert_eq!(tat, 1500);
+
+    // The budget refills while idle
+    assert_eq!(schedule(tat, 1500, 100, 3), (0, 1600));
+    assert_eq!(schedule(1500, 5000, 100, 3), (0, 5100));
+}
+
+#[tokio::test]
+async fn test_claim_shared_budget() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("default.ratelimit");
+
+    let mut waits = Vec::new();
+    for _ in 0..(BURST + 2) {
+        waits.push(claim_at(&path, 1000).await.unwrap());
+    }
+    assert_eq!(waits[BURST as usize - 1], 0);
+    assert_eq!(waits[BURST as usize], INTERVAL_MILLIS);
+    assert_eq!(waits[BURST as usize + 1], 2 * INTERVAL_MILLIS);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        (1000 + (BURST + 2) * INTERVAL_MILLIS).to_string()
+    );
+}
@@ -0,0 +1,321 @@
+use crate::api::{list_fills, list_orders, list_transfers, Fill, Transfer};
+use crate::ledger::{self, order_entry, Entry, Filter, Kind};
+use crate::transfers;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Amounts are compared as numbers; the exchange and hodl format them differently
+static TOLERANCE: f64 = 1e-8;
+
+// Discrepancies are short-lived and few, so boxing the entries isn't worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq)]
+pub enum Discrepancy {
+    /// On the exchange, but not in the ledger
+    Missing(Entry),
+    /// In the ledger, but not on the exchange
+    Extra(Entry),
+    /// In both, but with different `fields`
+    Mismatched {
+        local: Entry,
+        remote: Entry,
+        fields: Vec<&'static str>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileOptions {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Products to fetch fills for, on top of those of the orders in the ledger
+    pub products: Vec<String>,
+    /// Add entries that are on the exchange but not in the ledger
+    pub import: bool,
+    /// Bring mismatched entries in the ledger up to date with the exchange
+    pub update: bool,
+}
+
+fn parse(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
+/// The exchange formats times a few different ways; the ledger uses RFC 3339 in UTC
+fn normalize_time(time: &str) -> String {
+    DateTime::parse_from_rfc3339(time)
+        .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f%#z"))
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        })
+        .unwrap_or_else(|_| String::from(time))
+}
+
+fn in_period(created_at: &str, since: &Option<String>, until: &Option<String>) -> bool {
+    since.as_ref().is_none_or(|s| created_at >= s.as_str())
+        && until.as_ref().is_none_or(|u| created_at < u.as_str())
+}
+
+/// A done order, rebuilt from its fills; the exchange only lists open orders
+pub fn fills_to_entries(fills: &[Fill]) -> Vec<Entry> {
+    let mut by_order: BTreeMap<&str, Vec<&Fill>> = BTreeMap::new();
+    for fill in fills {
+        by_order.entry(&fill.order_id).or_default().push(fill);
+    }
+    by_order
+        .into_iter()
+        .map(|(order_id, fills)| {
+            let value: f64 = fills.iter().map(|f| parse(&f.price) * parse(&f.size)).sum();
+            let size: f64 = fills.iter().map(|f| parse(&f.size)).sum();
+            let fee: f64 = fills.iter().map(|f| parse(&f.fee)).sum();
+            let first = fills[0];
+            Entry {
+                recorded_at: normalize_time(&first.created_at),
+                updated_at: String::new(),
+                kind: Kind::Order,
+                exchange_id: String::from(order_id),
+                product: first.product_id.clone(),
+                side: Some(first.side.clone()),
+                amount: format!("{:.8}", value),
+                size: Some(format!("{:.8}", size)),
+                fee: Some(format!("{:.8}", fee)),
+                status: String::from(if fills.iter().all(|f| f.settled) {
+                    "settled"
+                } else {
+                    "done"
+                }),
+                raw: serde_json::to_string(&fills).expect("Fills are always serializable"),
+            }
+        })
+        .collect()
+}
+
+pub fn transfer_entry(transfer: &Transfer) -> Entry {
+    let details = transfer.details.as_ref();
+    let crypto = details.is_some_and(|d| d.get("crypto_address").is_some());
+    let kind = match (transfer.type_name.as_str(), crypto) {
+        ("deposit", _) => Kind::Deposit,
+        (_, true) => Kind::Withdrawal,
+        (_, false) => Kind::FiatWithdrawal,
+    };
+    let currency = details
+        .and_then(|d| d.get("currency"))
+        .and_then(|c| c.as_str())
+        .unwrap_or(if crypto { "" } else { "USD" });
+    Entry {
+        recorded_at: normalize_time(&transfer.created_at),
+        updated_at: String::new(),
+        kind,
+        exchange_id: transfer.id.clone(),
+        product: String::from(currency),
+        side: None,
+        amount: transfer.amount.clone(),
+        size: None,
+        fee: None,
+        status: format!("{:?}", transfers::status(transfer)).to_lowercase(),
+        raw: ledger::raw(transfer, &transfer.raw),
+    }
+}
+
+/// Statuses that mean the same thing: an order that is "done" may not have settled
+/// yet, and anything in flight (an open order, a transfer hodl recorded as "created")
+/// counts as pending
+fn same_status(local: &str, remote: &str) -> bool {
+    fn normalize(status: &str) -> &str {
+        match status {
+            "done" => "settled",
+            "created" | "processing" | "open" | "active" => "pending",
+            s => s,
+        }
+    }
+    normalize(local) == normalize(remote)
+}
+
+fn same_amount(local: &str, remote: &str) -> bool {
+    (parse(local) - parse(remote)).abs() < TOLERANCE
+}
+
+// Only compare what both sides know
+fn same_optional(local: &Option<String>, remote: &Option<String>) -> bool {
+    match (local, remote) {
+        (Some(l), Some(r)) => same_amount(l, r),
+        _ => true,
+    }
+}
+
+/// A cancelled order that never filled disappears from the exchange
+fn vanished(entry: &Entry) -> bool {
+    entry.kind == Kind::Order
+        && entry.status == "canceled"
+        && entry.size.as_deref().is_none_or(|s| parse(s) == 0.0)
+}
+
+/// Compare the ledger with the exchange, matching entries by type and exchange id
+pub fn diff(local: &[Entry], remote: &[Entry]) -> Vec<Discrepancy> {
+    let key = |e: &Entry| (e.kind.as_str(), e.exchange_id.clone());
+    let local_by_key: BTreeMap<_, &Entry> = local.iter().map(|e| (key(e), e)).collect();
+    let remote_keys: BTreeSet<_> = remote.iter().map(key).collect();
+
+    let mut discrepancies = Vec::new();
+    for r in remote {
+        let l = match local_by_key.get(&key(r)) {
+            Some(l) => *l,
+            None => {
+                discrepancies.push(Discrepancy::Missing(r.clone()));
+                continue;
+            }
+        };
+        let mut fields = Vec::new();
+        if !same_amount(&l.amount, &r.amount) {
+            fields.push("amount");
+        }
+        if !same_optional(&l.size, &r.size) {
+            fields.push("size");
+        }
+        if !same_optional(&l.fee, &r.fee) {
+            fields.push("fee");
+        }
+        if !same_status(&l.status, &r.status) {
+            fields.push("status");
+        }
+        if !fields.is_empty() {
+            discrepancies.push(Discrepancy::Mismatched {
+                local: l.clone(),
+                remote: r.clone(),
+                fields,
+            });
+        }
+    }
+    for l in local {
+        if !remote_keys.contains(&key(l)) && !vanished(l) {
+            discrepancies.push(Discrepancy::Extra(l.clone()));
+        }
+    }
+    discrepancies
+}
+
+pub fn format_discrepancy(discrepancy: &Discrepancy) -> String {
+    match discrepancy {
+        Discrepancy::Missing(e) => format!("missing     {}", ledger::format_entry(e)),
+        Discrepancy::Extra(e) => format!("extra       {}", ledger::format_entry(e)),
+        Discrepancy::Mismatched {
+            local,
+            remote,
+            fields,
+        } => {
+            let changes: Vec<String> = fields
+                .iter()
+                .map(|f| {
+                    let (l, r) = match *f {
+                        "amount" => (Some(&local.amount), Some(&remote.amount)),
+                        "size" => (local.size.as_ref(), remote.size.as_ref()),
+                        "fee" => (local.fee.as_ref(), remote.fee.as_ref()),
+                        _ => (Some(&local.status), Some(&remote.status)),
+                    };
+                    format!(
+                        "{} {} -> {}",
+                        f,
+                        l.map_or("?", |s| s.as_str()),
+                        r.map_or("?", |s| s.as_str())
+                    )
+                })
+                .collect();
+            format!(
+                "mismatched  {}  ({})",
+                ledger::format_entry(local),
+                changes.join(", ")
+            )
+        }
+    }
+}
+
+/// Fetch the orders, fills and transfers for the period from the exchange
+async fn fetch_remote(
+    products: &BTreeSet<String>,
+    options: &ReconcileOptions,
+) -> Option<Vec<Entry>> {
+    let mut remote = Vec::new();
+    for product in products {
+        let fills = list_fills(product).await?;
+        remote.extend(fills_to_entries(&fills));
+    }
+    // Orders that haven't filled yet have no fills, and open orders are listed on their own
+    let filled: BTreeSet<String> = remote.iter().map(|e| e.exchange_id.clone()).collect();
+    for order in list_orders(None).await? {
+        if !filled.contains(&order.id) {
+            remote.push(Entry {
+                recorded_at: normalize_time(&order.created_at),
+                ..order_entry(&order)
+            });
+        }
+    }
+    for transfer in list_transfers(None).await? {
+        remote.push(transfer_entry(&transfer));
+    }
+    remote.retain(|e| in_period(&e.recorded_at, &options.since, &options.until));
+    Some(remote)
+}
+
+/// Report how the ledger differs from the exchange for the period, importing or
+/// updating entries if asked. Returns whether everything was reconciled.
+pub async fn reconcile(options: &ReconcileOptions) -> Result<bool, String> {
+    let conn = ledger::open()?;
+    let filter = Filter {
+        since: options.since.clone(),
+        until: options.until.clone(),
+        ..Filter::default()
+    };
+    // The exchange doesn't list conversions, so there's nothing to compare them with
+    let mut local: Vec<Entry> = ledger::query(&conn, &filter)?
+        .into_iter()
+        .filter(|e| e.kind != Kind::Conversion)
+        .collect();
+
+    let mut products: BTreeSet<String> = options.products.iter().cloned().collect();
+    products.extend(
+        local
+            .iter()
+            .filter(|e| e.kind == Kind::Order)
+            .map(|e| e.product.clone()),
+    );
+    let remote = fetch_remote(&products, options)
+        .await
+        .ok_or("Failed to fetch activity from the exchange")?;
+
+    // An entry the exchange dates just inside the period may have been recorded just outside it
+    for r in &remote {
+        if !local
+            .iter()
+            .any(|l| l.kind == r.kind && l.exchange_id == r.exchange_id)
+        {
+            if let Some(l) = ledger::get(&conn, r.kind, &r.exchange_id)? {
+                local.push(l);
+            }
+        }
+    }
+
+    let discrepancies = diff(&local, &remote);
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut unresolved = 0;
+    for d in &discrepancies {
+        println!("{}", format_discrepancy(d));
+        match d {
+            // Imported entries keep the exchange's timestamp, so date filters still work
+            Discrepancy::Missing(r) if options.import => ledger::insert(&conn, r, &r.recorded_at)?,
+            Discrepancy::Mismatched { remote, .. } if options.update => {
+                ledger::update(&conn, remote, &now)?;
+            }
+            _ => unresolved += 1,
+        }
+    }
+    println!(
+        "{} discrepancies, {} left unresolved ({} fetched, {} in the ledger)",
+        discrepancies.len(),
+        unresolved,
+        remote.len(),
+        local.len()
+    );
+    Ok(unresolved == 0)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,109 @@
+use super::*;
+use crate::api::RawBody;
+
+fn fill(order_id: &str, price: &str, size: &str, fee: &str, settled: bool) -> Fill {
+    Fill {
+        trade_id: 1,
+        product_id: String::from("BTC-USD"),
+        order_id: String::from(order_id),
+        created_at: String::from("2021-01-10T00:07:00.123Z"),
+        liquidity: String::from("T"),
+        price: String::from(price),
+        size: String::from(size),
+        fee: String::from(fee),
+        side: String::from("buy"),
+        settled,
+    }
+}
+
+fn transfer(id: &str, completed: bool, details: serde_json::Value) -> Transfer {
+    Transfer {
+        id: String::from(id),
+        type_name: String::from("withdraw"),
+        created_at: String::from("2021-01-11 09:30:00.5+00"),
+        completed_at: if completed {
+            Some(String::from("2021-01-12 09:30:00+00"))
+        } else {
+            None
+        },
+        canceled_at: None,
+        processed_at: None,
+        amount: String::from("250.00"),
+        details: Some(details),
+        raw: RawBody::default(),
+    }
+}
+
+#[test]
+fn test_fills_to_entries() {
+    let entries = fills_to_entries(&[
+        fill("a", "30000", "0.0001", "0.015", true),
+        fill("a", "31000", "0.0002", "0.031", true),
+        fill("b", "30000", "0.001", "0.15", false),
+    ]);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].exchange_id, "a");
+    assert_eq!(entries[0].amount, "9.20000000");
+    assert_eq!(entries[0].size, Some(String::from("0.00030000")));
+    assert_eq!(entries[0].fee, Some(String::from("0.04600000")));
+    assert_eq!(entries[0].status, "settled");
+    assert_eq!(entries[0].recorded_at, "2021-01-10T00:07:00Z");
+    assert_eq!(entries[1].status, "done");
+}
+
+#[test]
+fn test_transfer_entry() {
+    let fiat = transfer_entry(&transfer("w", true, serde_json::json!({})));
+    assert_eq!(fiat.kind, Kind::FiatWithdrawal);
+    assert_eq!(fiat.product, "USD");
+    assert_eq!(fiat.status, "completed");
+    assert_eq!(fiat.recorded_at, "2021-01-11T09:30:00Z");
+
+    let crypto = transfer_entry(&transfer(
+        "c",
+        false,
+        serde_json::json!({"crypto_address": "bc1q", "currency": "BTC"}),
+    ));
+    assert_eq!(crypto.kind, Kind::Withdrawal);
+    assert_eq!(crypto.product, "BTC");
+    assert_eq!(crypto.status, "pending");
+}
+
+#[test]
+fn test_diff() {
+    let remote = fills_to_entries(&[
+        fill("same", "30000", "0.0001", "0.015", true),
+        fill("changed", "30000", "0.0001", "0.015", true),
+        fill("new", "30000", "0.0001", "0.015", true),
+    ]);
+    // Entries come back ordered by order id: changed, new, same
+    let mut changed = remote[0].clone();
+    changed.status = String::from("pending");
+    changed.fee = Some(String::from("0.02"));
+    let mut same = remote[2].clone();
+    // Formatted differently, but the same amount
+    same.amount = String::from("3.0");
+    let mut local = vec![changed, same];
+    let mut extra = remote[2].clone();
+    extra.exchange_id = String::from("gone");
+    local.push(extra);
+    let mut canceled = remote[2].clone();
+    canceled.exchange_id = String::from("canceled");
+    canceled.status = String::from("canceled");
+    canceled.size = Some(String::from("0"));
+    local.push(canceled);
+
+    let discrepancies = diff(&local, &remote);
+    assert_eq!(discrepancies.len(), 3);
+    match &discrepancies[0] {
+        Discrepancy::Mismatched { local, fields, .. } => {
+            assert_eq!(local.exchange_id, "changed");
+            assert_eq!(fields, &vec!["fee", "status"]);
+        }
+        d => panic!("Expected a mismatch, got {:?}", d),
+    }
+    assert!(matches!(&discrepancies[1], Discrepancy::Missing(e) if e.exchange_id == "new"));
+    assert!(matches!(&discrepancies[2], Discrepancy::Extra(e) if e.exchange_id == "gone"));
+    assert!(same_status("created", "processing"));
+    assert!(same_status("done", "settled"));
+}
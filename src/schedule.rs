@@ -0,0 +1,219 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone};
+
+// Never look further ahead than this for a matching day; a cron expression
+// like "0 0 31 2 *" simply never fires
+static MAX_DAYS_AHEAD: i64 = 366 * 5;
+
+/// When a scheduled job runs: a standard 5-field cron expression
+/// (minute hour day-of-month month day-of-week), or "every N days at HH:MM"
+#[derive(Debug, PartialEq)]
+pub enum Schedule {
+    Cron(CronSpec),
+    Every { days: u32, hour: u32, minute: u32 },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CronSpec {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days_of_month: Vec<u32>,
+    months: Vec<u32>,
+    days_of_week: Vec<u32>,
+    // Cron matches either day field when both are restricted
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+/// Parse one cron field (ex: "*/15", "1-5", "0,30") into the sorted values it allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => {
+                let step = part[i + 1..]
+                    .parse::<u32>()
+                    .map_err(|_| format!("'{}' has an invalid step", part))?;
+                if step == 0 {
+                    return Err(format!("'{}' has a step of zero", part));
+                }
+                (&part[..i], step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            let start = range[..i].parse::<u32>();
+            let end = range[i + 1..].parse::<u32>();
+            match (start, end) {
+                (Ok(s), Ok(e)) => (s, e),
+                _ => return Err(format!("'{}' is not a valid range", range)),
+            }
+        } else {
+            let v = range
+                .parse::<u32>()
+                .map_err(|_| format!("'{}' is not a number", range))?;
+            // "5/10" means every 10 starting at 5
+            if part.contains('/') {
+                (v, max)
+            } else {
+                (v, v)
+            }
+        };
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "'{}' is outside the allowed range {}-{}",
+                part, min, max
+            ));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_cron(expression: &str) -> Result<CronSpec, String> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    if fields.len() != 5 {
+        return Err(format!(
+            "'{}' should have 5 fields: minute hour day-of-month month day-of-week",
+            expression
+        ));
+    }
+    let mut days_of_week = parse_field(fields[4], 0, 7)?;
+    // Both 0 and 7 are Sunday
+    if days_of_week.contains(&7) {
+        days_of_week.retain(|d| *d != 7);
+        if !days_of_week.contains(&0) {
+            days_of_week.insert(0, 0);
+        }
+    }
+    Ok(CronSpec {
+        minutes: parse_field(fields[0], 0, 59)?,
+        hours: parse_field(fields[1], 0, 23)?,
+        days_of_month: parse_field(fields[2], 1, 31)?,
+        months: parse_field(fields[3], 1, 12)?,
+        days_of_week,
+        dom_restricted: fields[2] != "*",
+        dow_restricted: fields[4] != "*",
+    })
+}
+
+/// Parse "every N days at HH:MM" (or "every day at HH:MM")
+fn parse_every(expression: &str) -> Option<Schedule> {
+    let words: Vec<&str> = expression.split_whitespace().collect();
+    let (days, rest) = match words.as_slice() {
+        ["every", "day", rest @ ..] => (1, rest),
+        ["every", n, "days", rest @ ..] => (n.parse::<u32>().ok()?, rest),
+        _ => return None,
+    };
+    let time = match rest {
+        ["at", time] => time,
+        _ => return None,
+    };
+    let mut parts = time.splitn(2, ':');
+    let hour = parts.next()?.parse::<u32>().ok()?;
+    let minute = parts.next()?.parse::<u32>().ok()?;
+    if days == 0 || hour > 23 || minute > 59 {
+        return None;
+    }
+    Some(Schedule::Every { days, hour, minute })
+}
+
+pub fn parse(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    if expression.starts_with("every") {
+        return parse_every(expression).ok_or(format!(
+            "'{}' should look like 'every 3 days at 09:30'",
+            expression
+        ));
+    }
+    parse_cron(expression).map(Schedule::Cron)
+}
+
+impl Schedule {
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        match self {
+            Schedule::Cron(spec) => {
+                if !spec.months.contains(&date.month()) {
+                    return false;
+                }
+                let dom = spec.days_of_month.contains(&date.day());
+                let dow = spec
+                    .days_of_week
+                    .contains(&date.weekday().num_days_from_sunday());
+                match (spec.dom_restricted, spec.dow_restricted) {
+                    (true, true) => dom || dow,
+                    (true, false) => dom,
+                    (false, true) => dow,
+                    (false, false) => true,
+                }
+            }
+            // Counting from a fixed day keeps the cadence stable across restarts
+            Schedule::Every { days, .. } => date.num_days_from_ce() % *days as i32 == 0,
+        }
+    }
+
+    fn times(&self) -> Vec<(u32, u32)> {
+        match self {
+            Schedule::Cron(spec) => spec
+                .hours
+                .iter()
+                .flat_map(|h| spec.minutes.iter().map(move |m| (*h, *m)))
+                .collect(),
+            Schedule::Every { hour, minute, .. } => vec![(*hour, *minute)],
+        }
+    }
+
+    /// The first time strictly after `after` at which this schedule fires
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let times = self.times();
+        let first_day = after.date_naive();
+        for offset in 0..MAX_DAYS_AHEAD {
+            let date = first_day + Duration::days(offset);
+            if !self.runs_on(date) {
+                continue;
+            }
+            for (hour, minute) in &times {
+                // Times skipped by a DST change don't exist locally; skip them too
+                let candidate = match Local
+                    .from_local_datetime(&date.and_hms_opt(*hour, *minute, 0)?)
+                    .earliest()
+                {
+                    Some(c) => c,
+                    None => continue,
+                };
+                if candidate > after {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+
+    /// The times this schedule should have fired after `since`, up to and including
+    /// `now`, stopping after `limit` of them
+    pub fn missed_runs(
+        &self,
+        since: DateTime<Local>,
+        now: DateTime<Local>,
+        limit: usize,
+    ) -> Vec<DateTime<Local>> {
+        let mut runs = Vec::new();
+        let mut last = since;
+        while runs.len() < limit {
+            match self.next_after(last) {
+                Some(t) if t <= now => {
+                    runs.push(t);
+                    last = t;
+                }
+                _ => break,
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests;
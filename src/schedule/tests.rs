@@ -0,0 +1,103 @@
+use super::*;
+use chrono::Timelike;
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+}
+
+#[test]
+fn test_parse_field() {
+    assert_eq!(parse_field("*/15", 0, 59).unwrap(), vec![0, 15, 30, 45]);
+    assert_eq!(parse_field("1-5", 0, 6).unwrap(), vec![1, 2, 3, 4, 5]);
+    assert_eq!(parse_field("30,0", 0, 59).unwrap(), vec![0, 30]);
+    assert_eq!(parse_field("5/20", 0, 59).unwrap(), vec![5, 25, 45]);
+    assert!(parse_field("60", 0, 59).is_err());
+    assert!(parse_field("*/0", 0, 59).is_err());
+    assert!(parse_field("a", 0, 59).is_err());
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(
+        parse("every 3 days at 09:30").unwrap(),
+        Schedule::Every {
+            days: 3,
+            hour: 9,
+            minute: 30
+        }
+    );
+    assert_eq!(
+        parse("every day at 0:07").unwrap(),
+        Schedule::Every {
+            days: 1,
+            hour: 0,
+            minute: 7
+        }
+    );
+    assert!(parse("every 0 days at 09:30").is_err());
+    assert!(parse("every 3 days at 25:00").is_err());
+    assert!(parse("7 0 * *").is_err());
+    assert!(parse("7 0 * * *").is_ok());
+}
+
+#[test]
+fn test_cron_next_after() {
+    // Daily at 00:07, as in the README's crontab
+    let daily = parse("7 0 * * *").unwrap();
+    assert_eq!(
+        daily.next_after(at(2021, 1, 10, 0, 6)),
+        Some(at(2021, 1, 10, 0, 7))
+    );
+    assert_eq!(
+        daily.next_after(at(2021, 1, 10, 0, 7)),
+        Some(at(2021, 1, 11, 0, 7))
+    );
+
+    // Mondays at 09:00; 2021-01-10 is a Sunday
+    let mondays = parse("0 9 * * 1").unwrap();
+    assert_eq!(
+        mondays.next_after(at(2021, 1, 10, 12, 0)),
+        Some(at(2021, 1, 11, 9, 0))
+    );
+
+    // With both day fields restricted, either one matching is enough
+    let either = parse("0 12 1 * 5").unwrap();
+    assert_eq!(
+        either.next_after(at(2021, 1, 10, 0, 0)),
+        Some(at(2021, 1, 15, 12, 0))
+    );
+
+    assert_eq!(
+        parse("0 0 31 2 *")
+            .unwrap()
+            .next_after(at(2021, 1, 1, 0, 0)),
+        None
+    );
+}
+
+#[test]
+fn test_every_next_after() {
+    let every = parse("every 3 days at 08:00").unwrap();
+    let first = every.next_after(at(2021, 1, 10, 12, 0)).unwrap();
+    let second = every.next_after(first).unwrap();
+    assert_eq!((first.hour(), first.minute()), (8, 0));
+    assert_eq!(second - first, Duration::days(3));
+}
+
+#[test]
+fn test_missed_runs() {
+    let daily = parse("7 0 * * *").unwrap();
+    let since = at(2021, 1, 10, 0, 7);
+    assert!(daily
+        .missed_runs(since, at(2021, 1, 11, 0, 6), 10)
+        .is_empty());
+    assert_eq!(
+        daily.missed_runs(since, at(2021, 1, 13, 0, 7), 10),
+        vec![
+            at(2021, 1, 11, 0, 7),
+            at(2021, 1, 12, 0, 7),
+            at(2021, 1, 13, 0, 7)
+        ]
+    );
+    assert_eq!(daily.missed_runs(since, at(2021, 2, 1, 0, 0), 2).len(), 2);
+}
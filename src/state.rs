@@ -0,0 +1,51 @@
+use crate::config::hodl_home;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// What hodl remembers between runs, kept as JSON in `~/.hodl/state.json`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    /// Buys that couldn't be funded, to be retried on the next `buy`
+    #[serde(default)]
+    pub queued_buys: Vec<QueuedBuy>,
+    /// When each scheduled job, by name, last ran successfully (RFC 3339)
+    #[serde(default)]
+    pub last_runs: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QueuedBuy {
+    pub currency: String,
+    pub amount: f64,
+    pub queued_at: String,
+}
+
+pub fn state_path() -> PathBuf {
+    hodl_home().join("state.json")
+}
+
+pub fn load() -> Result<State, String> {
+    let path = state_path();
+    if !path.exists() {
+        return Ok(State::default());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid state in {}: {}", path.display(), e))
+}
+
+/// Write the state to a temporary file and rename it into place, so a crash
+/// mid-write never leaves a truncated state file behind
+pub fn save(state: &State) -> Result<(), String> {
+    let path = state_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(state).expect("State is always serializable");
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
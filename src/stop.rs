@@ -0,0 +1,132 @@
+use crate::api::{get_tick, place_stop_order, Order};
+use crate::ledger;
+use crate::logging::{self, fields, ErrorKind};
+
+/// `Loss` stops sell once the price falls to the stop price;
+/// `Entry` stops buy once the price rises to it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StopKind {
+    Loss,
+    Entry,
+}
+
+impl StopKind {
+    pub fn side(self) -> &'static str {
+        match self {
+            StopKind::Loss => "sell",
+            StopKind::Entry => "buy",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StopKind::Loss => "loss",
+            StopKind::Entry => "entry",
+        }
+    }
+}
+
+/// A stop must sit on the far side of the current price from where it triggers,
+/// otherwise the exchange would trigger it immediately
+pub fn validate_stop_price(
+    kind: StopKind,
+    stop_price: f64,
+    limit_price: Option<f64>,
+    current_price: f64,
+) -> Result<(), String> {
+    match kind {
+        StopKind::Loss if stop_price >= current_price => {
+            return Err(format!(
+                "A stop-sell price ({}) must be below the current price ({})",
+                stop_price, current_price
+            ));
+        }
+        StopKind::Entry if stop_price <= current_price => {
+            return Err(format!(
+                "A stop-buy price ({}) must be above the current price ({})",
+                stop_price, current_price
+            ));
+        }
+        _ => {}
+    }
+    match (kind, limit_price) {
+        (StopKind::Loss, Some(l)) if l > stop_price => Err(format!(
+            "A stop-sell limit price ({}) cannot be above its stop price ({})",
+            l, stop_price
+        )),
+        (StopKind::Entry, Some(l)) if l < stop_price => Err(format!(
+            "A stop-buy limit price ({}) cannot be below its stop price ({})",
+            l, stop_price
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check the stop price against the current ticker, then place the stop order
+pub async fn place_stop(
+    kind: StopKind,
+    currency: &str,
+    size: Option<&str>,
+    funds: Option<&str>,
+    stop_price: &str,
+    limit_price: Option<&str>,
+) -> Option<Order> {
+    let product_id = format!("{}-USD", currency);
+    let stop = match stop_price.parse::<f64>() {
+        Ok(p) => p,
+        Err(_) => {
+            logging::error(
+                &format!("'{}' is an invalid stop price", stop_price),
+                fields()
+                    .product(&product_id)
+                    .error(ErrorKind::InvalidRequest),
+            );
+            return None;
+        }
+    };
+    let limit = match limit_price.map(|l| l.parse::<f64>()) {
+        Some(Ok(l)) => Some(l),
+        Some(Err(_)) => {
+            logging::error(
+                &format!("'{}' is an invalid limit price", limit_price.unwrap_or("")),
+                fields()
+                    .product(&product_id)
+                    .error(ErrorKind::InvalidRequest),
+            );
+            return None;
+        }
+        None => None,
+    };
+
+    let tick = get_tick(&product_id).await?;
+    let current = match tick.price.parse::<f64>() {
+        Ok(p) => p,
+        Err(_) => {
+            logging::error(
+                &format!("'{}' is not a valid price", tick.price),
+                fields().product(&product_id).error(ErrorKind::Unexpected),
+            );
+            return None;
+        }
+    };
+    if let Err(e) = validate_stop_price(kind, stop, limit, current) {
+        logging::error(&e, fields().product(&product_id).error(ErrorKind::Refused));
+        return None;
+    }
+
+    let order = place_stop_order(
+        &product_id,
+        kind.side(),
+        kind.name(),
+        stop_price,
+        size,
+        funds,
+        limit_price,
+    )
+    .await?;
+    ledger::record(ledger::order_entry(&order));
+    Some(order)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,16 @@
+use super::*;
+
+#[test]
+fn test_validate_stop_price() {
+    // Stop-sells protect against a fall, so they sit below the current price
+    assert!(validate_stop_price(StopKind::Loss, 20000.0, None, 25000.0).is_ok());
+    assert!(validate_stop_price(StopKind::Loss, 26000.0, None, 25000.0).is_err());
+    assert!(validate_stop_price(StopKind::Loss, 20000.0, Some(19900.0), 25000.0).is_ok());
+    assert!(validate_stop_price(StopKind::Loss, 20000.0, Some(20100.0), 25000.0).is_err());
+
+    // Stop-entry buys trigger on a rise, so they sit above it
+    assert!(validate_stop_price(StopKind::Entry, 30000.0, None, 25000.0).is_ok());
+    assert!(validate_stop_price(StopKind::Entry, 25000.0, None, 25000.0).is_err());
+    assert!(validate_stop_price(StopKind::Entry, 30000.0, Some(30100.0), 25000.0).is_ok());
+    assert!(validate_stop_price(StopKind::Entry, 30000.0, Some(29900.0), 25000.0).is_err());
+}
@@ -0,0 +1,208 @@
+use crate::api::{get_candles, get_tick, Candlestick};
+use crate::logging::{self, fields, ErrorKind};
+use chrono::{Duration, Utc};
+
+static SECONDS_PER_DAY: i64 = 86400;
+static MAX_MA_DAYS: usize = 300;
+pub static DEFAULT_MA_DAYS: &str = "200";
+pub static DEFAULT_BANDS: &str = "-20:1.5,20:0.5";
+
+/// A band scales the purchase amount by `multiplier` once the price has moved at least
+/// `deviation` percent away from the moving average. Negative deviations are below the
+/// average, positive deviations are above it.
+#[derive(Debug, PartialEq)]
+pub struct Band {
+    pub deviation: f64,
+    pub multiplier: f64,
+}
+
+#[derive(Debug)]
+pub struct MovingAverageOptions {
+    pub days: usize,
+    pub bands: Vec<Band>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct ScaledAmount {
+    pub amount: f64,
+    pub explanation: String,
+}
+
+/// Parse bands from a string like `-20:1.5,20:0.5`
+pub fn parse_bands(input: &str) -> Result<Vec<Band>, String> {
+    let mut bands = Vec::new();
+    for pair in input.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let mut parts = pair.splitn(2, ':');
+        let deviation = parts
+            .next()
+            .and_then(|d| d.trim().parse::<f64>().ok())
+            .ok_or(format!("'{}' has an invalid deviation percentage", pair))?;
+        let multiplier = parts
+            .next()
+            .and_then(|m| m.trim().parse::<f64>().ok())
+            .ok_or(format!("'{}' has an invalid multiplier", pair))?;
+        if deviation == 0.0 {
+            return Err(format!("'{}': a band's deviation cannot be zero", pair));
+        }
+        if multiplier < 0.0 {
+            return Err(format!(
+                "'{}': a band's multiplier cannot be negative",
+                pair
+            ));
+        }
+        bands.push(Band {
+            deviation,
+            multiplier,
+        });
+    }
+    Ok(bands)
+}
+
+/// Simple moving average of the closing price of the `days` most recent candles.
+/// Candles are expected newest first, as the API returns them.
+pub fn moving_average(candles: &[Candlestick], days: usize) -> Option<f64> {
+    if days == 0 || candles.len() < days {
+        return None;
+    }
+    let sum: f64 = candles.iter().take(days).map(|c| c.4).sum();
+    Some(sum / days as f64)
+}
+
+/// Find the most extreme band that the given deviation (in percent) has crossed
+pub fn matching_band(deviation: f64, bands: &[Band]) -> Option<&Band> {
+    bands
+        .iter()
+        .filter(|b| {
+            (b.deviation < 0.0 && deviation <= b.deviation)
+                || (b.deviation > 0.0 && deviation >= b.deviation)
+        })
+        .max_by(|a, b| {
+            a.deviation
+                .abs()
+                .partial_cmp(&b.deviation.abs())
+                .expect("Band deviations must be comparable")
+        })
+}
+
+/// Scale `base` by the band matching the price's deviation from the moving average,
+/// then clamp the result between the optional min and max caps
+pub fn scale_amount(
+    base: f64,
+    price: f64,
+    average: f64,
+    options: &MovingAverageOptions,
+) -> ScaledAmount {
+    let deviation = (price - average) / average * 100.0;
+    let direction = if deviation < 0.0 { "below" } else { "above" };
+    let mut explanation = format!(
+        "Price ${:.2} is {:.1}% {} the {}-day moving average (${:.2}); ",
+        price,
+        deviation.abs(),
+        direction,
+        options.days,
+        average
+    );
+
+    let mut amount = match matching_band(deviation, &options.bands) {
+        Some(band) => {
+            let sign = if band.deviation < 0.0 { "<=" } else { ">=" };
+            explanation.push_str(&format!(
+                "applying the {}x band ({} {}%): ${:.2} -> ${:.2}",
+                band.multiplier,
+                sign,
+                band.deviation,
+                base,
+                base * band.multiplier
+            ));
+            base * band.multiplier
+        }
+        None => {
+            explanation.push_str(&format!("no band applies: ${:.2}", base));
+            base
+        }
+    };
+
+    if let Some(min) = options.min_amount {
+        if amount < min {
+            explanation.push_str(&format!(" (raised to the ${:.2} minimum)", min));
+            amount = min;
+        }
+    }
+    if let Some(max) = options.max_amount {
+        if amount > max {
+            explanation.push_str(&format!(" (capped at the ${:.2} maximum)", max));
+            amount = max;
+        }
+    }
+
+    // Market orders accept funds to the cent
+    let amount = (amount * 100.0).floor() / 100.0;
+    ScaledAmount {
+        amount,
+        explanation,
+    }
+}
+
+/// Compute the amount to buy by comparing the current tick to the product's
+/// moving average of daily closes
+pub async fn moving_average_amount(
+    base: f64,
+    product_id: &str,
+    options: &MovingAverageOptions,
+) -> Option<ScaledAmount> {
+    if options.days == 0 || options.days > MAX_MA_DAYS {
+        logging::error(
+            &format!(
+                "The moving average must span between 1 and {} days",
+                MAX_MA_DAYS
+            ),
+            fields()
+                .product(product_id)
+                .error(ErrorKind::InvalidRequest),
+        );
+        return None;
+    }
+
+    let end = Utc::now();
+    let start = end - Duration::seconds(SECONDS_PER_DAY * options.days as i64);
+    let candles = get_candles(
+        product_id,
+        &start.to_rfc3339(),
+        &end.to_rfc3339(),
+        SECONDS_PER_DAY,
+    )
+    .await?;
+    let average = match moving_average(&candles, options.days) {
+        Some(a) => a,
+        None => {
+            logging::error(
+                &format!(
+                    "Not enough history to compute a {}-day moving average (got {} days)",
+                    options.days,
+                    candles.len()
+                ),
+                fields().product(product_id).error(ErrorKind::Unexpected),
+            );
+            return None;
+        }
+    };
+
+    let tick = get_tick(product_id).await?;
+    let price = match tick.price.parse::<f64>() {
+        Ok(p) => p,
+        Err(_) => {
+            logging::error(
+                &format!("'{}' is not a valid price", tick.price),
+                fields().product(product_id).error(ErrorKind::Unexpected),
+            );
+            return None;
+        }
+    };
+
+    Some(scale_amount(base, price, average, options))
+}
+
+#[cfg(test)]
+mod tests;
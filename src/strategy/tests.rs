@@ -0,0 +1,69 @@
+use super::*;
+
+fn options(min_amount: Option<f64>, max_amount: Option<f64>) -> MovingAverageOptions {
+    MovingAverageOptions {
+        days: 200,
+        bands: parse_bands(DEFAULT_BANDS).unwrap(),
+        min_amount,
+        max_amount,
+    }
+}
+
+#[test]
+fn test_parse_bands() {
+    assert_eq!(
+        parse_bands("-20:1.5, 20:0.5").unwrap(),
+        vec![
+            Band {
+                deviation: -20.0,
+                multiplier: 1.5
+            },
+            Band {
+                deviation: 20.0,
+                multiplier: 0.5
+            },
+        ]
+    );
+    assert!(parse_bands("0:2").is_err());
+    assert!(parse_bands("-20").is_err());
+    assert!(parse_bands("abc:1").is_err());
+}
+
+#[test]
+fn test_moving_average() {
+    let candles = vec![
+        Candlestick(3, 0.0, 0.0, 0.0, 30.0, 0.0),
+        Candlestick(2, 0.0, 0.0, 0.0, 20.0, 0.0),
+        Candlestick(1, 0.0, 0.0, 0.0, 10.0, 0.0),
+    ];
+    assert_eq!(moving_average(&candles, 2), Some(25.0));
+    assert_eq!(moving_average(&candles, 3), Some(20.0));
+    assert_eq!(moving_average(&candles, 4), None);
+}
+
+#[test]
+fn test_scale_amount() {
+    // Within the bands the amount is unchanged
+    assert_eq!(
+        scale_amount(10.0, 105.0, 100.0, &options(None, None)).amount,
+        10.0
+    );
+    // 20% below the average buys more, 20% above buys less
+    assert_eq!(
+        scale_amount(10.0, 80.0, 100.0, &options(None, None)).amount,
+        15.0
+    );
+    assert_eq!(
+        scale_amount(10.0, 130.0, 100.0, &options(None, None)).amount,
+        5.0
+    );
+    // Caps are applied after scaling
+    assert_eq!(
+        scale_amount(10.0, 50.0, 100.0, &options(None, Some(12.0))).amount,
+        12.0
+    );
+    assert_eq!(
+        scale_amount(10.0, 150.0, 100.0, &options(Some(7.5), None)).amount,
+        7.5
+    );
+}
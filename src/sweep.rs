@@ -0,0 +1,135 @@
+use crate::api::{get_accounts, get_withdrawal_fee_estimate, withdraw_crypto};
+use crate::config::{Config, SweepRule};
+use crate::execution::floor_to_increment;
+use crate::ledger;
+use crate::logging::{self, fields, ErrorKind};
+use crate::withdraw::{check_destination, uses_destination_tag};
+
+// Withdrawals are accepted to the satoshi
+static WITHDRAWAL_INCREMENT: &str = "0.00000001";
+
+/// How much to sweep off the exchange, if the available balance has crossed the threshold
+pub fn sweep_amount(available: f64, rule: &SweepRule) -> Option<f64> {
+    if available <= rule.threshold || available <= rule.retain {
+        return None;
+    }
+    let amount = floor_to_increment(available - rule.retain, WITHDRAWAL_INCREMENT);
+    amount.parse::<f64>().ok().filter(|a| *a > 0.0)
+}
+
+/// Whether the network fee eats too much of the withdrawal to be worth sending yet
+pub fn fee_too_high(fee: f64, amount: f64, max_fee_percent: Option<f64>) -> bool {
+    match max_fee_percent {
+        Some(max) => fee / amount * 100.0 > max,
+        None => false,
+    }
+}
+
+/// Withdraw everything above each currency's retained amount to its cold storage
+/// address. Returns false if any sweep that should have happened failed.
+pub async fn sweep(config: &Config, dry_run: bool) -> bool {
+    if config.sweep.is_empty() {
+        logging::warn("No [sweep] rules are configured; nothing to do", fields());
+        return true;
+    }
+    let accounts = match get_accounts().await {
+        Some(a) => a,
+        None => return false,
+    };
+
+    let mut currencies: Vec<&String> = config.sweep.keys().collect();
+    currencies.sort();
+    let mut ok = true;
+    for currency in currencies {
+        let rule = &config.sweep[currency];
+        let available = accounts
+            .iter()
+            .find(|a| &a.currency == currency)
+            .and_then(|a| a.available.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        let amount = match sweep_amount(available, rule) {
+            Some(a) => a,
+            None => {
+                logging::info(
+                    &format!(
+                        "{} available is within the {} threshold; nothing to sweep",
+                        available, rule.threshold
+                    ),
+                    fields().product(currency),
+                );
+                continue;
+            }
+        };
+
+        let destination = match config.addresses.get(&rule.destination) {
+            Some(d) => d,
+            None => {
+                logging::error(
+                    &format!(
+                        "No address named '{}' in the address book",
+                        rule.destination
+                    ),
+                    fields().product(currency).error(ErrorKind::Config),
+                );
+                ok = false;
+                continue;
+            }
+        };
+        if let Err(e) = check_destination(currency, &rule.destination, destination) {
+            logging::error(&e, fields().product(currency).error(ErrorKind::Config));
+            ok = false;
+            continue;
+        }
+
+        let fee = match get_withdrawal_fee_estimate(currency, &destination.address).await {
+            Some(f) => f.fee.parse::<f64>().unwrap_or(0.0),
+            None => {
+                ok = false;
+                continue;
+            }
+        };
+        if fee_too_high(fee, amount, rule.max_fee_percent) {
+            logging::info(
+                &format!(
+                    "The {} fee is over {}% of {}; waiting for a larger balance",
+                    fee,
+                    rule.max_fee_percent.unwrap_or(0.0),
+                    amount
+                ),
+                fields().product(currency).amount(amount),
+            );
+            continue;
+        }
+
+        logging::info(
+            &format!(
+                "Sweeping (estimated fee {}) to '{}', leaving {}",
+                fee, rule.destination, rule.retain
+            ),
+            fields().product(currency).amount(amount),
+        );
+        if dry_run {
+            continue;
+        }
+        match withdraw_crypto(
+            &amount,
+            currency,
+            &destination.address,
+            destination.destination_tag.as_deref(),
+            uses_destination_tag(currency),
+        )
+        .await
+        {
+            Some(r) => {
+                ledger::record(ledger::withdrawal_entry(&r));
+                println!("{:#?}", r);
+                logging::debug(&format!("{:#?}", r), fields().product(currency));
+            }
+            None => ok = false,
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,27 @@
+use super::*;
+
+fn rule(threshold: f64, retain: f64) -> SweepRule {
+    SweepRule {
+        threshold,
+        retain,
+        destination: String::from("cold-btc"),
+        max_fee_percent: Some(1.0),
+    }
+}
+
+#[test]
+fn test_sweep_amount() {
+    assert_eq!(sweep_amount(0.4, &rule(0.5, 0.1)), None);
+    assert_eq!(sweep_amount(0.5, &rule(0.5, 0.1)), None);
+    assert_eq!(sweep_amount(0.6, &rule(0.5, 0.1)), Some(0.5));
+    assert_eq!(sweep_amount(0.123456789, &rule(0.1, 0.0)), Some(0.12345678));
+    // Retaining more than the balance never sweeps
+    assert_eq!(sweep_amount(0.6, &rule(0.5, 1.0)), None);
+}
+
+#[test]
+fn test_fee_too_high() {
+    assert!(!fee_too_high(0.0001, 0.5, Some(1.0)));
+    assert!(fee_too_high(0.0001, 0.005, Some(1.0)));
+    assert!(!fee_too_high(0.0001, 0.005, None));
+}
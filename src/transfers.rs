@@ -0,0 +1,90 @@
+use crate::api::{get_transfer, Transfer};
+use crate::logging::{self, fields};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+pub static DEFAULT_POLL_SECS: &str = "600";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TransferStatus {
+    Pending,
+    Processing,
+    Completed,
+    Canceled,
+}
+
+impl TransferStatus {
+    pub fn is_final(self) -> bool {
+        matches!(self, TransferStatus::Completed | TransferStatus::Canceled)
+    }
+}
+
+/// Transfers don't report a status; it follows from which timestamps are set
+pub fn status(transfer: &Transfer) -> TransferStatus {
+    if transfer.canceled_at.is_some() {
+        TransferStatus::Canceled
+    } else if transfer.completed_at.is_some() {
+        TransferStatus::Completed
+    } else if transfer.processed_at.is_some() {
+        TransferStatus::Processing
+    } else {
+        TransferStatus::Pending
+    }
+}
+
+/// One line per transfer: created, type, amount, status and id
+pub fn format_transfer(transfer: &Transfer) -> String {
+    format!(
+        "{}  {:<8}  {:>16}  {:<10}  {}",
+        transfer.created_at,
+        transfer.type_name,
+        transfer.amount,
+        format!("{:?}", status(transfer)).to_lowercase(),
+        transfer.id
+    )
+}
+
+/// Poll a transfer every `poll_secs` until it completes or is canceled, giving up
+/// after `timeout_secs` if set. A failed poll is retried on the next one. Returns the
+/// transfer as last seen, or None if it was never fetched.
+pub async fn wait_for_transfer(
+    transfer_id: &str,
+    poll_secs: u64,
+    timeout_secs: Option<u64>,
+) -> Option<Transfer> {
+    let started = Instant::now();
+    let mut last_seen = None;
+    loop {
+        // get_transfer logs a failure itself; one blip shouldn't end a wait that can take days
+        if let Some(transfer) = get_transfer(transfer_id).await {
+            if status(&transfer).is_final() {
+                return Some(transfer);
+            }
+            last_seen = Some(transfer);
+        }
+        let current = match &last_seen {
+            Some(t) => format!("{:?}", status(t)).to_lowercase(),
+            None => "unknown".to_string(),
+        };
+        if let Some(t) = timeout_secs {
+            if started.elapsed() >= Duration::from_secs(t) {
+                logging::warn(
+                    &format!("Gave up waiting for transfer {} after {}s", transfer_id, t),
+                    fields().status(&current),
+                );
+                return last_seen;
+            }
+        }
+        logging::info(
+            &format!(
+                "Transfer {} is {}; checking again in {}s",
+                transfer_id, current, poll_secs
+            ),
+            fields().status(&current),
+        );
+        sleep(Duration::from_secs(poll_secs)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,44 @@
+use super::*;
+use serde_json::json;
+
+fn transfer(processed: bool, completed: bool, canceled: bool) -> Transfer {
+    let at = |set: bool| {
+        if set {
+            json!("2020-03-12 00:14:12+00")
+        } else {
+            json!(null)
+        }
+    };
+    serde_json::from_value(json!({
+        "id": "19ac524d-8827-4246-a1b2-18dc5ca9472c",
+        "type": "deposit",
+        "created_at": "2020-03-12 00:14:12+00",
+        "processed_at": at(processed),
+        "completed_at": at(completed),
+        "canceled_at": at(canceled),
+        "amount": "50.00",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_status() {
+    assert_eq!(
+        status(&transfer(false, false, false)),
+        TransferStatus::Pending
+    );
+    assert_eq!(
+        status(&transfer(true, false, false)),
+        TransferStatus::Processing
+    );
+    assert_eq!(
+        status(&transfer(true, true, false)),
+        TransferStatus::Completed
+    );
+    assert_eq!(
+        status(&transfer(false, false, true)),
+        TransferStatus::Canceled
+    );
+    assert!(!TransferStatus::Processing.is_final());
+    assert!(TransferStatus::Canceled.is_final());
+}
@@ -0,0 +1,312 @@
+use crate::logging::{self, fields, ErrorKind};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+pub static FEED_URL: &str = "wss://ws-feed.pro.coinbase.com";
+static MIN_BACKOFF_MILLIS: u64 = 1000;
+static MAX_BACKOFF_MILLIS: u64 = 60_000;
+// The heartbeat channel sends a message every second, so this much silence means
+// the connection is dead even if it hasn't been closed
+static READ_TIMEOUT_SECS: u64 = 30;
+
+/// A message from the websocket feed. Only the fields hodl shows are kept.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    Subscriptions,
+    Heartbeat,
+    Ticker(TickerMessage),
+    Match(MatchMessage),
+    /// The most recent match, sent once on subscribing
+    LastMatch(MatchMessage),
+    Error {
+        message: String,
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TickerMessage {
+    pub product_id: String,
+    pub price: String,
+    pub open_24h: Option<String>,
+    pub volume_24h: Option<String>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub time: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MatchMessage {
+    pub product_id: String,
+    pub trade_id: u64,
+    pub price: String,
+    pub size: String,
+    /// The maker's side: "sell" means the trade was a buy at the ask
+    pub side: String,
+    pub time: String,
+}
+
+/// The latest of what the feed has said about a product
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Quote {
+    pub price: String,
+    pub open_24h: Option<String>,
+    pub volume_24h: Option<String>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub last_trade: Option<MatchMessage>,
+}
+
+impl Quote {
+    pub fn apply(&mut self, message: &FeedMessage) {
+        match message {
+            FeedMessage::Ticker(t) => {
+                self.price = t.price.clone();
+                self.open_24h = t.open_24h.clone().or_else(|| self.open_24h.take());
+                self.volume_24h = t.volume_24h.clone().or_else(|| self.volume_24h.take());
+                self.best_bid = t.best_bid.clone().or_else(|| self.best_bid.take());
+                self.best_ask = t.best_ask.clone().or_else(|| self.best_ask.take());
+            }
+            FeedMessage::Match(m) | FeedMessage::LastMatch(m) => {
+                self.price = m.price.clone();
+                self.last_trade = Some(m.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// One line per product: price, change over 24 hours, best bid and ask, volume and the last trade
+pub fn format_quote(product_id: &str, quote: &Quote) -> String {
+    let parse = |v: &Option<String>| v.as_deref().and_then(|s| s.parse::<f64>().ok());
+    let mut line = format!("{:<10} {:>12}", product_id, quote.price);
+    if let (Ok(price), Some(open)) = (quote.price.parse::<f64>(), parse(&quote.open_24h)) {
+        if open > 0.0 {
+            line.push_str(&format!(" {:>+7.2}% 24h", (price - open) / open * 100.0));
+        }
+    }
+    if let (Some(bid), Some(ask)) = (&quote.best_bid, &quote.best_ask) {
+        line.push_str(&format!("  bid {} ask {}", bid, ask));
+    }
+    if let Some(volume) = &quote.volume_24h {
+        line.push_str(&format!("  vol {}", volume));
+    }
+    if let Some(trade) = &quote.last_trade {
+        // The taker's side is the opposite of the maker's
+        let taker = if trade.side == "sell" { "buy" } else { "sell" };
+        line.push_str(&format!(
+            "  last {} {} at {}",
+            taker, trade.size, trade.time
+        ));
+    }
+    line
+}
+
+/// How long to wait before reconnecting after `failures` failed connections in a row
+pub fn backoff(failures: u32, min: Duration, max: Duration) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    min.saturating_mul(factor).min(max)
+}
+
+pub fn subscribe_message(products: &[String]) -> String {
+    serde_json::json!({
+        "type": "subscribe",
+        "product_ids": products,
+        "channels": ["heartbeat", "ticker", "matches"],
+    })
+    .to_string()
+}
+
+pub struct WatchOptions {
+    pub url: String,
+    pub products: Vec<String>,
+    /// Write each ticker and match as a JSON line instead of a price line per product
+    pub json: bool,
+    /// Redraw the price lines in place; only for a terminal
+    pub redraw: bool,
+    /// Give up after this many failed connections in a row; never if None
+    pub max_retries: Option<u32>,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl WatchOptions {
+    pub fn new(products: Vec<String>) -> WatchOptions {
+        WatchOptions {
+            url: String::from(FEED_URL),
+            products,
+            json: false,
+            redraw: false,
+            max_retries: None,
+            min_backoff: Duration::from_millis(MIN_BACKOFF_MILLIS),
+            max_backoff: Duration::from_millis(MAX_BACKOFF_MILLIS),
+        }
+    }
+}
+
+/// Write the output for one message, if it has any. `text` is the message as received
+fn show<W: Write>(
+    text: &str,
+    message: &FeedMessage,
+    quotes: &mut BTreeMap<String, Quote>,
+    options: &WatchOptions,
+    drawn: &mut bool,
+    out: &mut W,
+) -> Result<(), String> {
+    let product_id = match message {
+        FeedMessage::Ticker(t) => &t.product_id,
+        FeedMessage::Match(m) | FeedMessage::LastMatch(m) => &m.product_id,
+        _ => return Ok(()),
+    };
+    let quote = match quotes.get_mut(product_id) {
+        Some(q) => q,
+        None => return Ok(()),
+    };
+    quote.apply(message);
+
+    let written = if options.json {
+        // As received, with the fields hodl doesn't keep
+        writeln!(out, "{}", text)
+    } else if options.redraw {
+        // Move back up over the lines drawn last time and draw them all again
+        let mut frame = String::new();
+        if *drawn {
+            frame.push_str(&format!("\x1b[{}A", quotes.len()));
+        }
+        for (product_id, quote) in quotes.iter() {
+            frame.push_str(&format!("\r\x1b[2K{}\n", format_quote(product_id, quote)));
+        }
+        *drawn = true;
+        write!(out, "{}", frame)
+    } else {
+        writeln!(out, "{}", format_quote(product_id, quote))
+    };
+    written
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write output: {}", e))
+}
+
+/// Connect, subscribe and show messages until the connection drops. Returns whether
+/// any messages were received, or an error the feed won't get over by reconnecting.
+async fn stream<W: Write>(
+    options: &WatchOptions,
+    quotes: &mut BTreeMap<String, Quote>,
+    drawn: &mut bool,
+    out: &mut W,
+) -> Result<bool, String> {
+    let (mut socket, _) = match connect_async(options.url.as_str()).await {
+        Ok(s) => s,
+        Err(e) => {
+            logging::warn(
+                &format!("Failed to connect to {}: {}", options.url, e),
+                fields().error(ErrorKind::Http),
+            );
+            return Ok(false);
+        }
+    };
+    if let Err(e) = socket
+        .send(Message::Text(subscribe_message(&options.products)))
+        .await
+    {
+        logging::warn(
+            &format!("Failed to subscribe: {}", e),
+            fields().error(ErrorKind::Http),
+        );
+        return Ok(false);
+    }
+    logging::info(
+        &format!("Subscribed to {}", options.products.join(", ")),
+        fields(),
+    );
+
+    let mut received = false;
+    loop {
+        let next = timeout(Duration::from_secs(READ_TIMEOUT_SECS), socket.next()).await;
+        let text = match next {
+            Ok(Some(Ok(Message::Text(t)))) => t,
+            Ok(Some(Ok(Message::Close(_)))) | Ok(None) => {
+                logging::warn("The feed closed the connection", fields());
+                return Ok(received);
+            }
+            // Pings are answered by the library
+            Ok(Some(Ok(_))) => continue,
+            Ok(Some(Err(e))) => {
+                logging::warn(
+                    &format!("Lost the connection to the feed: {}", e),
+                    fields().error(ErrorKind::Http),
+                );
+                return Ok(received);
+            }
+            Err(_) => {
+                logging::warn(
+                    &format!("Nothing from the feed for {}s", READ_TIMEOUT_SECS),
+                    fields().error(ErrorKind::Http),
+                );
+                return Ok(received);
+            }
+        };
+        let message: FeedMessage = match serde_json::from_str(&text) {
+            Ok(m) => m,
+            Err(e) => {
+                logging::debug(
+                    &format!("Ignoring a message that didn't parse ({}): {}", e, text),
+                    fields().error(ErrorKind::Unexpected),
+                );
+                continue;
+            }
+        };
+        if let FeedMessage::Error { message, reason } = &message {
+            return Err(format!(
+                "The feed refused the subscription: {}{}",
+                message,
+                reason
+                    .as_ref()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default()
+            ));
+        }
+        received = true;
+        show(&text, &message, quotes, options, drawn, out)?;
+    }
+}
+
+/// Stream the ticker and matches of `options.products`, reconnecting with exponential
+/// backoff whenever the connection drops. Runs until the retries run out, if ever
+pub async fn watch<W: Write>(options: &WatchOptions, out: &mut W) -> Result<(), String> {
+    let mut quotes: BTreeMap<String, Quote> = options
+        .products
+        .iter()
+        .map(|p| (p.clone(), Quote::default()))
+        .collect();
+    let mut drawn = false;
+    let mut failures = 0;
+    loop {
+        if stream(options, &mut quotes, &mut drawn, out).await? {
+            failures = 0;
+        }
+        failures += 1;
+        if options.max_retries.is_some_and(|max| failures > max) {
+            return Err(format!(
+                "Gave up on {} after {} failed connections",
+                options.url, failures
+            ));
+        }
+        let wait = backoff(failures, options.min_backoff, options.max_backoff);
+        logging::info(
+            &format!("Reconnecting in {}s", wait.as_secs_f64()),
+            fields(),
+        );
+        sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,101 @@
+use super::*;
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_async;
+
+static TICKER: &str = r#"{"type":"ticker","sequence":1,"product_id":"BTC-USD","price":"41000.00","open_24h":"40000.00","volume_24h":"1234.5","low_24h":"39000.00","high_24h":"42000.00","best_bid":"40999.99","best_ask":"41000.00","side":"buy","time":"2022-01-01T00:00:00.000000Z","trade_id":7,"last_size":"0.01"}"#;
+static MATCH: &str = r#"{"type":"match","trade_id":8,"sequence":2,"maker_order_id":"a","taker_order_id":"b","time":"2022-01-01T00:00:01.000000Z","product_id":"BTC-USD","size":"0.5","price":"41001.00","side":"sell"}"#;
+
+#[test]
+fn test_parse_feed_messages() {
+    match serde_json::from_str::<FeedMessage>(TICKER).unwrap() {
+        FeedMessage::Ticker(t) => {
+            assert_eq!(t.product_id, "BTC-USD");
+            assert_eq!(t.price, "41000.00");
+            assert_eq!(t.best_bid.as_deref(), Some("40999.99"));
+        }
+        m => panic!("Expected a ticker, got {:?}", m),
+    }
+    match serde_json::from_str::<FeedMessage>(MATCH).unwrap() {
+        FeedMessage::Match(m) => assert_eq!((m.trade_id, m.size.as_str()), (8, "0.5")),
+        m => panic!("Expected a match, got {:?}", m),
+    }
+    let heartbeat = r#"{"type":"heartbeat","sequence":3,"last_trade_id":8,"product_id":"BTC-USD","time":"2022-01-01T00:00:02Z"}"#;
+    assert_eq!(
+        serde_json::from_str::<FeedMessage>(heartbeat).unwrap(),
+        FeedMessage::Heartbeat
+    );
+    let unknown = r#"{"type":"l2update","product_id":"BTC-USD","changes":[]}"#;
+    assert_eq!(
+        serde_json::from_str::<FeedMessage>(unknown).unwrap(),
+        FeedMessage::Other
+    );
+}
+
+#[test]
+fn test_format_quote() {
+    let mut quote = Quote::default();
+    quote.apply(&serde_json::from_str(TICKER).unwrap());
+    assert_eq!(
+        format_quote("BTC-USD", &quote),
+        "BTC-USD        41000.00   +2.50% 24h  bid 40999.99 ask 41000.00  vol 1234.5"
+    );
+    quote.apply(&serde_json::from_str(MATCH).unwrap());
+    assert_eq!(quote.price, "41001.00");
+    assert!(
+        format_quote("BTC-USD", &quote).ends_with("  last buy 0.5 at 2022-01-01T00:00:01.000000Z")
+    );
+}
+
+#[test]
+fn test_backoff() {
+    let min = Duration::from_secs(1);
+    let max = Duration::from_secs(60);
+    let waits: Vec<u64> = (1..=8).map(|n| backoff(n, min, max).as_secs()).collect();
+    assert_eq!(waits, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+    assert_eq!(backoff(100, min, max), max);
+}
+
+#[tokio::test]
+async fn test_watch_reconnects() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    // Stands in for the feed: each connection gets one message, then is dropped
+    let server = tokio::spawn(async move {
+        let mut subscriptions = Vec::new();
+        for message in [TICKER, MATCH] {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(tcp).await.unwrap();
+            if let Some(Ok(Message::Text(s))) = socket.next().await {
+                subscriptions.push(s);
+            }
+            socket
+                .send(Message::Text(String::from(message)))
+                .await
+                .unwrap();
+            socket.close(None).await.unwrap();
+        }
+        subscriptions
+    });
+
+    let mut options = WatchOptions::new(vec![String::from("BTC-USD")]);
+    options.url = url;
+    options.json = true;
+    options.max_retries = Some(2);
+    options.min_backoff = Duration::from_millis(10);
+    options.max_backoff = Duration::from_millis(20);
+    let mut out = Vec::new();
+    let result = watch(&options, &mut out).await;
+    assert!(result.unwrap_err().starts_with("Gave up"));
+
+    let subscriptions = server.await.unwrap();
+    assert_eq!(subscriptions.len(), 2);
+    let subscribe: serde_json::Value = serde_json::from_str(&subscriptions[0]).unwrap();
+    assert_eq!(subscribe["product_ids"], serde_json::json!(["BTC-USD"]));
+
+    // Each message is written as it came, fields hodl doesn't use included
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("{}\n{}\n", TICKER, MATCH)
+    );
+}
@@ -0,0 +1,148 @@
+use crate::api::{get_withdrawal_fee_estimate, withdraw_crypto, WithdrawalResponse};
+use crate::config::Address;
+use crate::ledger;
+use crate::logging::{self, fields, ErrorKind};
+use std::io::{self, BufRead, Write};
+
+static BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+static RIPPLE_BASE58: &str = "rpshnaf39wBUDNEGHJKLM4PQRST7VWXYZ2bcdeCg65jkm8oFqi1tuvAxyz";
+static BECH32: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+static BASE32: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// Ethereum and the ERC-20 tokens Coinbase sends over it share one address format
+static ETHEREUM_CURRENCIES: &[&str] = &[
+    "ETH", "ETC", "USDC", "USDT", "DAI", "LINK", "UNI", "AAVE", "COMP", "MKR", "MATIC", "SHIB",
+];
+
+fn all_in(s: &str, alphabet: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| alphabet.contains(c))
+}
+
+fn is_base58(s: &str, prefixes: &[char], min: usize, max: usize) -> bool {
+    s.len() >= min && s.len() <= max && s.starts_with(prefixes) && all_in(s, BASE58)
+}
+
+fn is_bech32(s: &str, hrp: &str, min: usize, max: usize) -> bool {
+    let prefix = format!("{}1", hrp);
+    s.len() >= min && s.len() <= max && s.starts_with(&prefix) && all_in(&s[prefix.len()..], BECH32)
+}
+
+/// Whether withdrawals of `currency` are routed by a destination tag or memo
+pub fn uses_destination_tag(currency: &str) -> bool {
+    matches!(currency, "XRP" | "XLM" | "EOS" | "ATOM" | "HBAR")
+}
+
+/// Sanity-check the shape of an address for the given currency. This catches
+/// pasting an address for the wrong coin; it does not verify checksums.
+pub fn validate_address(currency: &str, address: &str, tag: Option<&str>) -> Result<(), String> {
+    let valid = match currency {
+        "BTC" => is_base58(address, &['1', '3'], 26, 35) || is_bech32(address, "bc", 42, 62),
+        "LTC" => is_base58(address, &['L', 'M', '3'], 26, 35) || is_bech32(address, "ltc", 43, 63),
+        "BCH" => {
+            let a = address.trim_start_matches("bitcoincash:");
+            (a.len() == 42 && a.starts_with(&['q', 'p'][..]) && all_in(a, BECH32))
+                || is_base58(address, &['1', '3'], 26, 35)
+        }
+        "DOGE" => is_base58(address, &['D', 'A', '9'], 34, 34),
+        "XRP" => {
+            address.len() >= 25
+                && address.len() <= 35
+                && address.starts_with('r')
+                && all_in(address, RIPPLE_BASE58)
+        }
+        "XLM" => address.len() == 56 && address.starts_with('G') && all_in(address, BASE32),
+        "ALGO" => address.len() == 58 && all_in(address, BASE32),
+        c if ETHEREUM_CURRENCIES.contains(&c) => {
+            address.len() == 42
+                && address.starts_with("0x")
+                && address[2..].chars().all(|c| c.is_ascii_hexdigit())
+        }
+        _ => !address.is_empty() && !address.contains(char::is_whitespace),
+    };
+    if !valid {
+        return Err(format!("'{}' is not a valid {} address", address, currency));
+    }
+
+    match (currency, tag) {
+        ("XRP", Some(t)) if t.parse::<u32>().is_err() => Err(format!(
+            "'{}' is not a valid XRP destination tag; it must be a number",
+            t
+        )),
+        (c, Some(_)) if !uses_destination_tag(c) => {
+            Err(format!("{} withdrawals do not take a destination tag", c))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Make sure an address book entry can receive `currency`
+pub fn check_destination(currency: &str, name: &str, destination: &Address) -> Result<(), String> {
+    if destination.currency != currency {
+        return Err(format!(
+            "Address '{}' is for {}, not {}",
+            name, destination.currency, currency
+        ));
+    }
+    validate_address(
+        currency,
+        &destination.address,
+        destination.destination_tag.as_deref(),
+    )
+    .map_err(|e| format!("Address '{}' in the config is invalid: {}", name, e))
+}
+
+/// Ask a yes/no question on the terminal; anything but "y" or "yes" is a no
+pub fn confirm(prompt: &str) -> bool {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush().expect("Failed to flush stdout");
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Withdraw `amount` of `currency` to an address from the address book, showing the
+/// estimated network fee first. Unless `assume_yes`, the user must confirm.
+pub async fn withdraw(
+    currency: &str,
+    amount: f64,
+    name: &str,
+    destination: &Address,
+    assume_yes: bool,
+) -> Option<WithdrawalResponse> {
+    if let Err(e) = check_destination(currency, name, destination) {
+        logging::error(&e, fields().product(currency).error(ErrorKind::Config));
+        return None;
+    }
+    let tag = destination.destination_tag.as_deref();
+
+    let estimate = get_withdrawal_fee_estimate(currency, &destination.address).await?;
+    println!(
+        "Withdrawing {} {} to '{}' ({}{})",
+        amount,
+        currency,
+        name,
+        destination.address,
+        tag.map(|t| format!(", tag {}", t)).unwrap_or_default()
+    );
+    println!("Estimated network fee: {} {}", estimate.fee, currency);
+    if !assume_yes && !confirm("Proceed with the withdrawal?") {
+        println!("Withdrawal cancelled");
+        return None;
+    }
+
+    let withdrawal = withdraw_crypto(
+        &amount,
+        currency,
+        &destination.address,
+        tag,
+        uses_destination_tag(currency),
+    )
+    .await?;
+    ledger::record(ledger::withdrawal_entry(&withdrawal));
+    Some(withdrawal)
+}
+
+#[cfg(test)]
+mod tests;
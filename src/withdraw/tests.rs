@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn test_validate_address() {
+    assert!(validate_address("BTC", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", None).is_ok());
+    assert!(validate_address("BTC", "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", None).is_ok());
+    assert!(validate_address("BTC", "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq", None).is_ok());
+    assert!(validate_address("ETH", "0x32Be343B94f860124dC4fEe278FDCBD38C102D88", None).is_ok());
+    assert!(validate_address("USDC", "0x32Be343B94f860124dC4fEe278FDCBD38C102D88", None).is_ok());
+    assert!(validate_address("XRP", "rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY", Some("12345")).is_ok());
+
+    // An address for another coin is rejected
+    assert!(validate_address("BTC", "0x32Be343B94f860124dC4fEe278FDCBD38C102D88", None).is_err());
+    assert!(validate_address("ETH", "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq", None).is_err());
+    // As are typos outside the alphabet (0 isn't base58) and truncated addresses
+    assert!(validate_address("BTC", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNV0", None).is_err());
+    assert!(validate_address("ETH", "0x32Be343B94f860124dC4fEe278FDCBD38C102D8", None).is_err());
+}
+
+#[test]
+fn test_validate_destination_tag() {
+    assert!(validate_address("XRP", "rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY", Some("abc")).is_err());
+    assert!(validate_address("BTC", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", Some("12345")).is_err());
+}